@@ -7,10 +7,10 @@ use gtk::prelude::*;
 use gtk::{StatusIcon, Window, WindowType};
 
 use audio_input::AudioUpdater;
-use audio_input::{get_sources, PaSourceInfo};
-use audio_input::{AudioProcessor, FRAMES, AudioFrame};
-use shared_data::{ContinueState, StateHolder, Rc, RefCell};
-use config::{read_config, write_config, ConvertTo};
+use audio_input::{get_sources, watch_sources, PaSourceInfo};
+use audio_input::{AudioProcessor, AudioFrame};
+use shared_data::{ContinueState, SourceTable, StateHolder, Rc, RefCell};
+use config::{read_config, read_audio_config, write_config, ConvertTo};
 use ui::{default_status_icon, set_icon_callbacks};
 use instance::GtkVisualizerInstance;
 use message::UpdateMessage;
@@ -21,6 +21,9 @@ pub struct GtkVisualizerApp {
     // when assigning: give current, then increment
     current_id_n: StateHolder<usize>,
     pub instances: StateHolder<HashMap<usize, GtkVisualizerInstance>>,
+    sources: StateHolder<SourceTable>,
+    // source changes from the audio updater
+    source_receiver: Receiver<UpdateMessage>,
     icon: StatusIcon,
     program_continue: ContinueState, /* whether the program whould continue, shared by app, all instances, and audio updater */
 }
@@ -38,20 +41,42 @@ impl GtkVisualizerApp {
 
         let program_continue = ContinueState::new(true);
 
+        // watch for hot plugging before asking for the sources, so none get missed in between.
+        // whatever shows up twice gets sorted out by the updater
+        let (event_send, event_recv) = channel();
+        {
+            let (ready_send, ready_recv) = channel();
+            ::std::thread::spawn(move || {
+                if let Err(e) = watch_sources(event_send, ready_send) {
+                    println!("{}", e);
+                }
+            });
+            // also returns if the watcher gave up
+            let _ = ready_recv.recv();
+        }
+
         // initialize everything the audio updater needs
-        let (default_source_name, sources) = get_sources()
+        let (default_source_name, mut sources) = get_sources()
             .expect("Could not get any audio devices");
-        let default_source_index = default_source_index(&default_source_name, &sources).unwrap();
+        let audio_config = read_audio_config().unwrap();
+        for source_config in audio_config.source_configs().iter() {
+            match source_config.to_source_info() {
+                Ok(info) => sources.push(Some(info)),
+                Err(e) => println!("Skipping audio source: {}", e),
+            }
+        }
+        let default_source_index = default_source_index(&default_source_name, &sources);
         let num_sources = sources.len();
         let mut instances = HashMap::<usize, GtkVisualizerInstance>::new();
         let (update_send, update_recv) = channel();
-        let current_data = vec![Arc::new(Mutex::new(None)); num_sources];
+        let (source_send, source_recv) = channel();
         let audio_processor_mappings = (0..num_sources).map(|_| None).collect();
 
+        let source_table = Rc::new(RefCell::new(SourceTable::new(sources.clone(), default_source_index)));
         let instance_configs = read_config().unwrap();
         let mut instance_id = 0;
         for config in instance_configs {
-            let instance = config.to_instance(instance_id, &current_data, update_send.clone());
+            let instance = config.to_instance(instance_id, source_table.clone(), update_send.clone());
             instances.insert(instance_id, instance);
             instance_id += 1;
         }
@@ -60,9 +85,11 @@ impl GtkVisualizerApp {
             let program_continue = program_continue.clone();
             let mut updater = AudioUpdater::new(&default_source_name,
                                                 sources,
+                                                audio_config.processors,
                                                 audio_processor_mappings,
                                                 update_recv,
-                                                current_data.clone(),
+                                                event_recv,
+                                                source_send,
                                                 program_continue.clone());
             ::std::thread::spawn(move || {
                 // startup the audio updater
@@ -79,14 +106,44 @@ impl GtkVisualizerApp {
         let this = GtkVisualizerApp {
             current_id_n: Rc::new(RefCell::new(instance_id)),
             instances: Rc::new(RefCell::new(instances)),
+            sources: source_table,
+            source_receiver: source_recv,
             icon: icon,
             program_continue: program_continue.clone(),
         };
-        set_icon_callbacks(&this.icon, this.current_id_n.clone(), this.instances.clone(), current_data, update_send, default_source_index, program_continue);
+        set_icon_callbacks(&this.icon, this.current_id_n.clone(), this.instances.clone(), this.sources.clone(), update_send, program_continue);
         this
     }
 
     pub fn main_iteration(&mut self) -> Result<(), String> {
+        // keep up with sources being plugged in and out
+        while let Ok(message) = self.source_receiver.try_recv() {
+            match message {
+                UpdateMessage::SourceAdded(index, info) => {
+                    for instance in self.instances.borrow().values() {
+                        instance.source_added(index, &info);
+                    }
+                    self.sources.borrow_mut().add(index, info);
+                }
+                UpdateMessage::SourceData(index, data) => {
+                    self.sources.borrow_mut().set_data(index, data);
+                }
+                UpdateMessage::SourceRemoved(index) => {
+                    self.sources.borrow_mut().remove(index);
+                }
+                UpdateMessage::DefaultChanged(index) => {
+                    self.sources.borrow_mut().set_default(index);
+                    let info = self.sources.borrow().get(index).map(|(_, info)| info.clone());
+                    if let Some(info) = info {
+                        for instance in self.instances.borrow().values() {
+                            instance.default_changed(index, &info);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
         // iterate instances
         let mut to_remove = Vec::new();
         for (id, instance) in (*self.instances.borrow_mut()).iter_mut() {
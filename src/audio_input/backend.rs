@@ -0,0 +1,127 @@
+use std::panic::{self, AssertUnwindSafe};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use pa_simple::{Builder, Reader};
+
+use super::{PaSourceInfo, SourceKind};
+use super::file::FileBackend;
+use super::generator::GeneratorBackend;
+use super::pipe::PipeBackend;
+
+// Anything that can hand out interleaved 16 bit audio frames.
+// AudioProcessor only ever talks to this, so pulseaudio is just one implementation
+// and other kinds of sources (files, pipes, generated signals) can sit beside it.
+pub trait CaptureBackend {
+    // fill the entire buffer with interleaved frames, blocking until they are available
+    fn read(&mut self, buffer: &mut [i16]) -> Result<(), String>;
+    fn channels(&self) -> usize;
+    fn rate(&self) -> u32;
+}
+
+pub struct PulseBackend {
+    channels: usize,
+    rate: u32,
+    recorder: Reader<i16>,
+}
+
+impl PulseBackend {
+    pub fn new(source: &PaSourceInfo) -> Result<PulseBackend, String> {
+        let recorder = try!(catch_pa_panic(|| {
+            Builder::new("visualizers", "visualizers")
+                .channels(source.channels)
+                .rate(source.rate)
+                .device(&source.name)
+                .reader_i16()
+        }));
+        Ok(PulseBackend {
+            channels: source.channels as usize,
+            rate: source.rate,
+            recorder: recorder,
+        })
+    }
+}
+
+// pa_simple panics instead of returning errors, keep a failing stream from taking the
+// whole updater down with it
+fn catch_pa_panic<T, F: FnOnce() -> T>(f: F) -> Result<T, String> {
+    panic::catch_unwind(AssertUnwindSafe(f)).map_err(|e| {
+        if let Some(s) = e.downcast_ref::<String>() {
+            s.clone()
+        } else if let Some(s) = e.downcast_ref::<&str>() {
+            s.to_string()
+        } else {
+            "PulseAudio stream failed".to_string()
+        }
+    })
+}
+
+impl CaptureBackend for PulseBackend {
+    fn read(&mut self, buffer: &mut [i16]) -> Result<(), String> {
+        let recorder = &mut self.recorder;
+        catch_pa_panic(move || recorder.read(buffer))
+    }
+
+    fn channels(&self) -> usize {
+        self.channels
+    }
+
+    fn rate(&self) -> u32 {
+        self.rate
+    }
+}
+
+// create the backend that records from the given source
+pub fn open_backend(source: &PaSourceInfo) -> Result<Box<CaptureBackend>, String> {
+    match source.kind {
+        SourceKind::Pulse => Ok(Box::new(try!(PulseBackend::new(source)))),
+        SourceKind::File(ref path) => Ok(Box::new(try!(FileBackend::open(path)))),
+        SourceKind::Pipe(ref spec) => Ok(Box::new(PipeBackend::new(spec))),
+        SourceKind::Generator(ref spec) => Ok(Box::new(GeneratorBackend::new(spec))),
+        SourceKind::Replay(ref path) => {
+            Err(format!("{} is a recording of frames, not audio", path.display()))
+        }
+    }
+}
+
+// for sources that hand out floating point samples in the range -1.0 to 1.0
+pub fn float_to_i16(sample: f32) -> i16 {
+    (sample.max(-1.).min(1.) * ::std::i16::MAX as f32) as i16
+}
+
+// Keeps sources that aren't driven by a sound card running at their real rate.
+// Blocks the same way a live device read would: until the frames handed out so far
+// would have been played.
+pub struct Pacer {
+    rate: u32,
+    started: Option<Instant>,
+    frames: u64,
+}
+
+impl Pacer {
+    pub fn new(rate: u32) -> Pacer {
+        Pacer {
+            rate: rate,
+            started: None,
+            frames: 0,
+        }
+    }
+
+    pub fn wait(&mut self, frames: usize) {
+        let started = match self.started {
+            Some(instant) => instant,
+            None => {
+                let now = Instant::now();
+                self.started = Some(now);
+                now
+            }
+        };
+        self.frames += frames as u64;
+        let due_ns = self.frames * 1_000_000_000 / self.rate as u64;
+        let due = started + Duration::new(due_ns / 1_000_000_000, (due_ns % 1_000_000_000) as u32);
+        let now = Instant::now();
+        if due > now {
+            sleep(due - now);
+        }
+    }
+}
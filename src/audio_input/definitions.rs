@@ -1,2 +1,303 @@
-pub type AudioFrame = Vec<Vec<f64>>;
-pub const FRAMES: usize = 256;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// limits on the fourier transform size a processor can be set to, both powers of 2
+pub const MIN_FFT_SIZE: usize = 256;
+pub const MAX_FFT_SIZE: usize = 8192;
+pub const DEFAULT_FFT_SIZE: usize = 256;
+
+// what the channels of a frame are
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ChannelLayout {
+    Mono,
+    Stereo,
+    // anything with more channels, in the order the source delivers them
+    Multi(usize),
+}
+
+impl ChannelLayout {
+    pub fn from_channels(channels: usize) -> Self {
+        match channels {
+            1 => ChannelLayout::Mono,
+            2 => ChannelLayout::Stereo,
+            n => ChannelLayout::Multi(n),
+        }
+    }
+}
+
+impl Default for ChannelLayout {
+    fn default() -> Self {
+        ChannelLayout::Mono
+    }
+}
+
+// where a channel is meant to be played, named after pulseaudio's positions
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ChannelPosition {
+    Mono,
+    FrontLeft,
+    FrontRight,
+    FrontCenter,
+    Lfe,
+    RearLeft,
+    RearRight,
+    RearCenter,
+    SideLeft,
+    SideRight,
+    // anything without a speaker of its own
+    Aux,
+}
+
+// every position, recordings store a channel's position as its index in here
+pub const CHANNEL_POSITIONS: [ChannelPosition; 11] = [ChannelPosition::Mono,
+                                                      ChannelPosition::FrontLeft,
+                                                      ChannelPosition::FrontRight,
+                                                      ChannelPosition::FrontCenter,
+                                                      ChannelPosition::Lfe,
+                                                      ChannelPosition::RearLeft,
+                                                      ChannelPosition::RearRight,
+                                                      ChannelPosition::RearCenter,
+                                                      ChannelPosition::SideLeft,
+                                                      ChannelPosition::SideRight,
+                                                      ChannelPosition::Aux];
+
+impl ChannelPosition {
+    // pa_simple opens streams without a channel map, so pulseaudio delivers the channels in its
+    // default map: the AIFF order for up to 6 channels and aux channels after those
+    pub fn pulse_order(channels: usize) -> Vec<ChannelPosition> {
+        use self::ChannelPosition::*;
+        let known: &[ChannelPosition] = match channels {
+            1 => &[Mono],
+            2 => &[FrontLeft, FrontRight],
+            3 => &[FrontLeft, FrontRight, FrontCenter],
+            4 => &[FrontLeft, FrontCenter, FrontRight, RearCenter],
+            5 => &[FrontLeft, FrontRight, FrontCenter, RearLeft, RearRight],
+            _ => &[FrontLeft, RearLeft, FrontCenter, FrontRight, RearRight, Lfe],
+        };
+        extend_aux(known, channels)
+    }
+
+    // the default order of wav and flac files, also taken for raw pcm and test signals
+    pub fn file_order(channels: usize) -> Vec<ChannelPosition> {
+        use self::ChannelPosition::*;
+        let known: &[ChannelPosition] = match channels {
+            1 => &[Mono],
+            2 => &[FrontLeft, FrontRight],
+            3 => &[FrontLeft, FrontRight, FrontCenter],
+            4 => &[FrontLeft, FrontRight, RearLeft, RearRight],
+            5 => &[FrontLeft, FrontRight, FrontCenter, RearLeft, RearRight],
+            6 => &[FrontLeft, FrontRight, FrontCenter, Lfe, RearLeft, RearRight],
+            7 => &[FrontLeft, FrontRight, FrontCenter, Lfe, RearCenter, SideLeft, SideRight],
+            _ => &[FrontLeft, FrontRight, FrontCenter, Lfe, RearLeft, RearRight, SideLeft, SideRight],
+        };
+        extend_aux(known, channels)
+    }
+
+    pub fn from_code(code: u8) -> ChannelPosition {
+        CHANNEL_POSITIONS.get(code as usize).cloned().unwrap_or(ChannelPosition::Aux)
+    }
+
+    pub fn code(&self) -> u8 {
+        CHANNEL_POSITIONS.iter().position(|p| p == self).unwrap() as u8
+    }
+}
+
+fn extend_aux(known: &[ChannelPosition], channels: usize) -> Vec<ChannelPosition> {
+    let mut positions: Vec<ChannelPosition> = known.iter().cloned().take(channels).collect();
+    positions.resize(channels, ChannelPosition::Aux);
+    positions
+}
+
+// the index of every wanted position among positions, None if one is missing
+pub fn find_positions(positions: &[ChannelPosition], wanted: &[ChannelPosition]) -> Option<Vec<usize>> {
+    wanted.iter().map(|w| positions.iter().position(|p| p == w)).collect()
+}
+
+// what a frame needs to carry, each instance asks for what its style draws
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FrameContent {
+    pub spectrum: bool,
+    pub waveform: bool,
+    // spectra of the sum and difference of the first two channels
+    pub mid_side: bool,
+}
+
+impl FrameContent {
+    pub fn spectrum() -> Self {
+        FrameContent {
+            spectrum: true,
+            waveform: false,
+            mid_side: false,
+        }
+    }
+
+    pub fn union(self, other: FrameContent) -> Self {
+        FrameContent {
+            spectrum: self.spectrum || other.spectrum,
+            waveform: self.waveform || other.waveform,
+            mid_side: self.mid_side || other.mid_side,
+        }
+    }
+}
+
+// rms of the full scale magnitudes in the usual three ranges
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct BandEnergies {
+    pub bass: f64,
+    pub mid: f64,
+    pub treble: f64,
+}
+
+// levels of a source as meters show them
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Loudness {
+    // per channel, fractions of full scale over the samples that are new in the frame
+    pub rms: Vec<f64>,
+    pub true_peak: Vec<f64>,
+    // highest true peak of all channels lately, held for a while and then falling back
+    pub peak_hold: f64,
+    // K-weighted loudness of the last 3 s in LUFS, once 3 s have been measured
+    pub short_term: Option<f64>,
+    // gated loudness since the source was opened in LUFS
+    pub integrated: Option<f64>,
+}
+
+// the few numbers of a frame that get shown as text, cheap to copy unlike the whole frame
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FrameSummary {
+    pub bpm: Option<f64>,
+    // LUFS
+    pub short_term: Option<f64>,
+    // held true peak as a fraction of full scale
+    pub peak_hold: f64,
+}
+
+// Everything one processor run produces for a source.
+#[derive(Debug, Default, PartialEq)]
+pub struct AudioFrame {
+    // magnitude of the full (mirrored) spectrum, one vec of fft size bins per channel
+    pub magnitudes: Vec<Vec<f64>>,
+    // the samples that went into the transform as fractions of full scale, one vec per
+    // channel, oldest first. only there when an instance asked for the waveform
+    pub samples: Option<Vec<Vec<f64>>>,
+    // magnitudes of (left + right) / 2 and (left - right) / 2, when an instance asked for them
+    pub mid_side: Option<Vec<Vec<f64>>>,
+    pub rate: u32,
+    // Hz covered by every bin of magnitudes
+    pub bin_width: f64,
+    pub layout: ChannelLayout,
+    // counts up by one for every frame a processor produces
+    pub sequence: u64,
+    // ns since the unix epoch when the audio was captured
+    pub timestamp: u64,
+    // spectral flux, how much the spectrum grew since the previous frame
+    pub onset_strength: f64,
+    // whether a beat was detected in this frame
+    pub beat: bool,
+    // beats detected so far, frames can be skipped so compare this to see if one happened
+    pub beats: u64,
+    // timestamp of the latest beat
+    pub last_beat: Option<u64>,
+    pub energies: BandEnergies,
+    // tempo estimated from the last few seconds of onsets, if there is a steady one
+    pub bpm: Option<f64>,
+    // where the frame falls between two beats, 0 on a beat rising to 1 right before the next
+    pub beat_phase: f64,
+    pub loudness: Loudness,
+}
+
+impl AudioFrame {
+    pub fn channels(&self) -> usize {
+        self.magnitudes.len()
+    }
+
+    // left and right spectrum, a mono frame gives its only channel for both
+    pub fn stereo(&self) -> (&[f64], &[f64]) {
+        match self.magnitudes.len() {
+            0 => (&[], &[]),
+            1 => (&self.magnitudes[0], &self.magnitudes[0]),
+            _ => (&self.magnitudes[0], &self.magnitudes[1]),
+        }
+    }
+
+    pub fn summary(&self) -> FrameSummary {
+        FrameSummary {
+            bpm: self.bpm,
+            short_term: self.loudness.short_term,
+            peak_hold: self.loudness.peak_hold,
+        }
+    }
+}
+
+// clone_from reuses the buffers, frames get copied around on every draw
+impl Clone for AudioFrame {
+    fn clone(&self) -> Self {
+        AudioFrame {
+            magnitudes: self.magnitudes.clone(),
+            samples: self.samples.clone(),
+            mid_side: self.mid_side.clone(),
+            rate: self.rate,
+            bin_width: self.bin_width,
+            layout: self.layout,
+            sequence: self.sequence,
+            timestamp: self.timestamp,
+            onset_strength: self.onset_strength,
+            beat: self.beat,
+            beats: self.beats,
+            last_beat: self.last_beat,
+            energies: self.energies,
+            bpm: self.bpm,
+            beat_phase: self.beat_phase,
+            loudness: self.loudness.clone(),
+        }
+    }
+
+    fn clone_from(&mut self, source: &Self) {
+        self.magnitudes.clone_from(&source.magnitudes);
+        self.samples.clone_from(&source.samples);
+        self.mid_side.clone_from(&source.mid_side);
+        self.rate = source.rate;
+        self.bin_width = source.bin_width;
+        self.layout = source.layout;
+        self.sequence = source.sequence;
+        self.timestamp = source.timestamp;
+        self.onset_strength = source.onset_strength;
+        self.beat = source.beat;
+        self.beats = source.beats;
+        self.last_beat = source.last_beat;
+        self.energies = source.energies;
+        self.bpm = source.bpm;
+        self.beat_phase = source.beat_phase;
+        self.loudness.rms.clone_from(&source.loudness.rms);
+        self.loudness.true_peak.clone_from(&source.loudness.true_peak);
+        self.loudness.peak_hold = source.loudness.peak_hold;
+        self.loudness.short_term = source.loudness.short_term;
+        self.loudness.integrated = source.loudness.integrated;
+    }
+}
+
+pub fn timestamp_now() -> u64 {
+    let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or(Duration::from_secs(0));
+    since_epoch.as_secs() * 1_000_000_000 + since_epoch.subsec_nanos() as u64
+}
+
+#[test]
+fn test_frame_stereo() {
+    let mono = AudioFrame { magnitudes: vec![vec![1.]], ..AudioFrame::default() };
+    assert_eq!(mono.stereo(), (&[1.][..], &[1.][..]));
+    let stereo = AudioFrame { magnitudes: vec![vec![1.], vec![2.], vec![3.]], ..AudioFrame::default() };
+    assert_eq!(stereo.stereo(), (&[1.][..], &[2.][..]));
+}
+
+#[test]
+fn test_channel_positions() {
+    use self::ChannelPosition::*;
+    let pulse = ChannelPosition::pulse_order(6);
+    assert_eq!(find_positions(&pulse, &[FrontLeft, FrontRight]), Some(vec![0, 3]));
+    assert_eq!(find_positions(&pulse, &[Lfe]), Some(vec![5]));
+    // nothing past 6 channels has a position in pulseaudio's default map
+    assert_eq!(find_positions(&ChannelPosition::pulse_order(8), &[SideLeft, SideRight]), None);
+    let file = ChannelPosition::file_order(8);
+    assert_eq!(find_positions(&file, &[SideLeft, SideRight]), Some(vec![6, 7]));
+    assert_eq!(ChannelPosition::file_order(10)[8..], [Aux, Aux]);
+    assert!(CHANNEL_POSITIONS.iter().all(|p| ChannelPosition::from_code(p.code()) == *p));
+}
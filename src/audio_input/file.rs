@@ -0,0 +1,261 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+use claxon::{Block, FlacReader};
+use claxon::metadata::StreamInfo;
+use hound::{SampleFormat, WavReader, WavSpec};
+
+use super::{ChannelPosition, PaSourceInfo, SourceKind};
+use super::backend::{CaptureBackend, Pacer, float_to_i16};
+
+// Plays back a wav or flac file at its native rate and channel count, looping at the end.
+// Samples get decoded as they are read, so long files don't have to fit in memory.
+pub struct FileBackend {
+    path: PathBuf,
+    channels: usize,
+    rate: u32,
+    decoder: Decoder,
+    // whether anything was read since the last rewind, an empty file would loop forever
+    played: bool,
+    pacer: Pacer,
+}
+
+impl FileBackend {
+    pub fn open(path: &Path) -> Result<FileBackend, String> {
+        let decoder = try!(Decoder::open(path));
+        let (channels, rate) = (decoder.channels(), decoder.rate());
+        if channels == 0 {
+            return Err(format!("{} has no channels", path.display()));
+        }
+        Ok(FileBackend {
+            path: path.to_path_buf(),
+            channels: channels,
+            rate: rate,
+            decoder: decoder,
+            played: false,
+            pacer: Pacer::new(rate),
+        })
+    }
+}
+
+impl CaptureBackend for FileBackend {
+    fn read(&mut self, buffer: &mut [i16]) -> Result<(), String> {
+        for frame in buffer.chunks_mut(self.channels) {
+            // a trailing partial frame counts as the end, so the channels stay aligned
+            while !try!(self.decoder.next_frame(frame)) {
+                if !self.played {
+                    return Err(format!("{} contains no audio", self.path.display()));
+                }
+                self.decoder = try!(Decoder::open(&self.path));
+                self.played = false;
+            }
+            self.played = true;
+        }
+        self.pacer.wait(buffer.len() / self.channels);
+        Ok(())
+    }
+
+    fn channels(&self) -> usize {
+        self.channels
+    }
+
+    fn rate(&self) -> u32 {
+        self.rate
+    }
+}
+
+// read just the header of the file to describe it as a source
+pub fn probe_file(path: &Path) -> Result<PaSourceInfo, String> {
+    let (rate, channels) = match try!(FileFormat::from_path(path)) {
+        FileFormat::Wav => {
+            let spec = try!(WavReader::open(path).map_err(|e| format!("{}", e))).spec();
+            (spec.sample_rate, spec.channels as u32)
+        }
+        FileFormat::Flac => {
+            let info = try!(FlacReader::open(path).map_err(|e| format!("{}", e))).streaminfo();
+            (info.sample_rate, info.channels)
+        }
+    };
+    if channels == 0 || channels > ::std::u8::MAX as u32 {
+        return Err(format!("{} has an unsupported channel count of {}",
+                           path.display(),
+                           channels));
+    }
+    Ok(PaSourceInfo {
+        name: format!("file:{}", path.display()),
+        description: match path.file_name() {
+            Some(name) => name.to_string_lossy().into_owned(),
+            None => path.display().to_string(),
+        },
+        rate: rate,
+        channels: channels as u8,
+        positions: ChannelPosition::file_order(channels as usize),
+        kind: SourceKind::File(path.to_path_buf()),
+    })
+}
+
+enum FileFormat {
+    Wav,
+    Flac,
+}
+
+impl FileFormat {
+    fn from_path(path: &Path) -> Result<FileFormat, String> {
+        let extension = path.extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_lowercase());
+        match extension.as_ref().map(|e| e.as_str()) {
+            Some("wav") | Some("wave") => Ok(FileFormat::Wav),
+            Some("flac") => Ok(FileFormat::Flac),
+            _ => Err(format!("{} is not a wav or flac file", path.display())),
+        }
+    }
+}
+
+enum Decoder {
+    Wav {
+        reader: WavReader<BufReader<File>>,
+        spec: WavSpec,
+    },
+    Flac {
+        reader: FlacReader<File>,
+        info: StreamInfo,
+        // the block being played and the next frame in it
+        block: Block,
+        position: u32,
+    },
+}
+
+impl Decoder {
+    fn open(path: &Path) -> Result<Decoder, String> {
+        match try!(FileFormat::from_path(path)) {
+            FileFormat::Wav => {
+                let reader = try!(WavReader::open(path).map_err(|e| format!("{}", e)));
+                let spec = reader.spec();
+                Ok(Decoder::Wav {
+                    reader: reader,
+                    spec: spec,
+                })
+            }
+            FileFormat::Flac => {
+                let reader = try!(FlacReader::open(path).map_err(|e| format!("{}", e)));
+                let info = reader.streaminfo();
+                Ok(Decoder::Flac {
+                    reader: reader,
+                    info: info,
+                    block: Block::empty(),
+                    position: 0,
+                })
+            }
+        }
+    }
+
+    fn channels(&self) -> usize {
+        match *self {
+            Decoder::Wav { ref spec, .. } => spec.channels as usize,
+            Decoder::Flac { ref info, .. } => info.channels as usize,
+        }
+    }
+
+    fn rate(&self) -> u32 {
+        match *self {
+            Decoder::Wav { ref spec, .. } => spec.sample_rate,
+            Decoder::Flac { ref info, .. } => info.sample_rate,
+        }
+    }
+
+    // fill one interleaved frame, false at the end of the file
+    fn next_frame(&mut self, frame: &mut [i16]) -> Result<bool, String> {
+        match *self {
+            Decoder::Wav { ref mut reader, spec } => {
+                for sample in frame.iter_mut() {
+                    let decoded = match spec.sample_format {
+                        SampleFormat::Int => {
+                            reader.samples::<i32>()
+                                .next()
+                                .map(|s| s.map(|s| int_to_i16(s, spec.bits_per_sample as u32)))
+                        }
+                        SampleFormat::Float => {
+                            reader.samples::<f32>().next().map(|s| s.map(float_to_i16))
+                        }
+                    };
+                    match decoded {
+                        Some(s) => *sample = try!(s.map_err(|e| format!("{}", e))),
+                        None => return Ok(false),
+                    }
+                }
+                Ok(true)
+            }
+            Decoder::Flac { ref mut reader, info, ref mut block, ref mut position } => {
+                while *position >= block.duration() {
+                    // decode into the previous block's buffer
+                    let buffer = ::std::mem::replace(block, Block::empty()).into_buffer();
+                    let next = reader.blocks().read_next_or_eof(buffer);
+                    match try!(next.map_err(|e| format!("{}", e))) {
+                        Some(next) => *block = next,
+                        None => return Ok(false),
+                    }
+                    if block.channels() as usize != frame.len() {
+                        return Err("Channel count changes within the file".to_string());
+                    }
+                    *position = 0;
+                }
+                for (channel, sample) in frame.iter_mut().enumerate() {
+                    let decoded = block.sample(channel as u32, *position);
+                    *sample = int_to_i16(decoded, info.bits_per_sample);
+                }
+                *position += 1;
+                Ok(true)
+            }
+        }
+    }
+}
+
+// bring an integer sample of any bit depth into the 16 bit range the processor expects
+fn int_to_i16(sample: i32, bits_per_sample: u32) -> i16 {
+    if bits_per_sample > 16 {
+        (sample >> (bits_per_sample - 16)) as i16
+    } else {
+        (sample << (16 - bits_per_sample)) as i16
+    }
+}
+
+#[test]
+fn test_int_to_i16() {
+    assert_eq!(int_to_i16(-8388608, 24), -32768);
+    assert_eq!(int_to_i16(8388607, 24), 32767);
+    assert_eq!(int_to_i16(127, 8), 32512);
+    assert_eq!(int_to_i16(1234, 16), 1234);
+}
+
+#[test]
+fn test_wav_round_trip() {
+    use hound::WavWriter;
+
+    let path = ::std::env::temp_dir().join("visualizers_test_wav_round_trip.wav");
+    let spec = WavSpec {
+        channels: 2,
+        sample_rate: 22050,
+        bits_per_sample: 16,
+        sample_format: SampleFormat::Int,
+    };
+    {
+        let mut writer = WavWriter::create(&path, spec).unwrap();
+        for i in 0..6i16 {
+            writer.write_sample(i * 100).unwrap();
+        }
+        writer.finalize().unwrap();
+    }
+
+    let info = probe_file(&path).unwrap();
+    assert_eq!(info.rate, 22050);
+    assert_eq!(info.channels, 2);
+
+    let mut backend = FileBackend::open(&path).unwrap();
+    let mut buffer = vec![0; 8];
+    backend.read(&mut buffer).unwrap();
+    // loops back around to the start after the last frame
+    assert_eq!(buffer.as_slice(), &[0, 100, 200, 300, 400, 500, 0, 100]);
+    ::std::fs::remove_file(&path).unwrap();
+}
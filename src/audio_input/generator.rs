@@ -0,0 +1,244 @@
+use std::f64::consts::PI;
+
+use super::{ChannelPosition, PaSourceInfo, SourceKind};
+use super::backend::{CaptureBackend, Pacer, float_to_i16};
+
+// length and pitch of a metronome click
+const CLICK_SECS: f64 = 0.02;
+const CLICK_FREQUENCY: f64 = 2000.;
+
+// what a generator source plays
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Signal {
+    Sine { frequency: f64 },
+    // rises exponentially from one frequency to the other, then starts over
+    Sweep { from: f64, to: f64, seconds: f64 },
+    WhiteNoise,
+    // equal energy per octave
+    PinkNoise,
+    // single full height samples, frequency times a second
+    ImpulseTrain { frequency: f64 },
+    Metronome { bpm: f64 },
+}
+
+impl Signal {
+    fn describe(&self) -> (String, String) {
+        match *self {
+            Signal::Sine { frequency } => {
+                (format!("sine-{}", frequency), format!("{} Hz sine", frequency))
+            }
+            Signal::Sweep { from, to, seconds } => {
+                (format!("sweep-{}-{}-{}", from, to, seconds),
+                 format!("{} to {} Hz sweep over {} s", from, to, seconds))
+            }
+            Signal::WhiteNoise => ("white-noise".to_string(), "White noise".to_string()),
+            Signal::PinkNoise => ("pink-noise".to_string(), "Pink noise".to_string()),
+            Signal::ImpulseTrain { frequency } => {
+                (format!("impulses-{}", frequency), format!("{} Hz impulse train", frequency))
+            }
+            Signal::Metronome { bpm } => {
+                (format!("metronome-{}", bpm), format!("Metronome at {} BPM", bpm))
+            }
+        }
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        let positive = match *self {
+            Signal::Sine { frequency } |
+            Signal::ImpulseTrain { frequency } => frequency > 0.,
+            Signal::Sweep { from, to, seconds } => from > 0. && to > 0. && seconds > 0.,
+            Signal::Metronome { bpm } => bpm > 0.,
+            Signal::WhiteNoise | Signal::PinkNoise => true,
+        };
+        if positive {
+            Ok(())
+        } else {
+            Err(format!("{:?} needs positive frequencies and lengths", self))
+        }
+    }
+}
+
+// Synthetic test signal, every channel gets the same samples.
+#[derive(Clone, Debug)]
+pub struct GeneratorSpec {
+    pub signal: Signal,
+    // peak level as a fraction of full scale
+    pub amplitude: f64,
+    pub rate: u32,
+    pub channels: u8,
+}
+
+pub fn generator_source_info(spec: GeneratorSpec) -> Result<PaSourceInfo, String> {
+    try!(spec.signal.validate());
+    if spec.rate == 0 || spec.channels == 0 {
+        return Err("Test signals need a nonzero rate and channel count".to_string());
+    }
+    // written this way round so NaN fails too
+    if !(spec.amplitude >= 0. && spec.amplitude <= 1.) {
+        return Err(format!("Test signal amplitude {} is not between 0 and 1", spec.amplitude));
+    }
+    let (name, description) = spec.signal.describe();
+    Ok(PaSourceInfo {
+        name: format!("generator:{}", name),
+        description: format!("Test signal: {}", description),
+        rate: spec.rate,
+        channels: spec.channels,
+        positions: ChannelPosition::file_order(spec.channels as usize),
+        kind: SourceKind::Generator(spec),
+    })
+}
+
+pub struct GeneratorBackend {
+    spec: GeneratorSpec,
+    pacer: Pacer,
+    // samples generated so far
+    position: u64,
+    // phase of the sweep in cycles, kept running so restarts don't click
+    phase: f64,
+    noise: NoiseSource,
+    // state of the pink noise filter
+    pink: [f64; 7],
+}
+
+impl GeneratorBackend {
+    pub fn new(spec: &GeneratorSpec) -> GeneratorBackend {
+        GeneratorBackend {
+            spec: spec.clone(),
+            pacer: Pacer::new(spec.rate),
+            position: 0,
+            phase: 0.,
+            noise: NoiseSource::new(0x2545F4914F6CDD1D),
+            pink: [0.; 7],
+        }
+    }
+
+    // the next sample, from -1 to 1
+    fn next_sample(&mut self) -> f64 {
+        let rate = self.spec.rate as f64;
+        let t = self.position as f64 / rate;
+        self.position += 1;
+        match self.spec.signal {
+            Signal::Sine { frequency } => (2. * PI * frequency * t).sin(),
+            Signal::Sweep { from, to, seconds } => {
+                let elapsed = t % seconds;
+                let frequency = from * (to / from).powf(elapsed / seconds);
+                self.phase = (self.phase + frequency / rate) % 1.;
+                (2. * PI * self.phase).sin()
+            }
+            Signal::WhiteNoise => self.noise.next(),
+            Signal::PinkNoise => {
+                // Paul Kellet's filter, peaks rarely go past 8 so scale that down to full height
+                let white = self.noise.next();
+                let b = &mut self.pink;
+                b[0] = 0.99886 * b[0] + white * 0.0555179;
+                b[1] = 0.99332 * b[1] + white * 0.0750759;
+                b[2] = 0.96900 * b[2] + white * 0.1538520;
+                b[3] = 0.86650 * b[3] + white * 0.3104856;
+                b[4] = 0.55000 * b[4] + white * 0.5329522;
+                b[5] = -0.7616 * b[5] - white * 0.0168980;
+                let pink = b[0] + b[1] + b[2] + b[3] + b[4] + b[5] + b[6] + white * 0.5362;
+                b[6] = white * 0.115926;
+                (pink / 8.).max(-1.).min(1.)
+            }
+            Signal::ImpulseTrain { frequency } => {
+                let period = (rate / frequency).max(1.);
+                if (t * rate) % period < 1. { 1. } else { 0. }
+            }
+            Signal::Metronome { bpm } => {
+                let since_click = t % (60. / bpm);
+                if since_click < CLICK_SECS {
+                    let decay = 1. - since_click / CLICK_SECS;
+                    decay * (2. * PI * CLICK_FREQUENCY * since_click).sin()
+                } else {
+                    0.
+                }
+            }
+        }
+    }
+}
+
+impl CaptureBackend for GeneratorBackend {
+    fn read(&mut self, buffer: &mut [i16]) -> Result<(), String> {
+        let channels = self.spec.channels as usize;
+        for frame in buffer.chunks_mut(channels) {
+            let sample = float_to_i16((self.next_sample() * self.spec.amplitude) as f32);
+            for s in frame.iter_mut() {
+                *s = sample;
+            }
+        }
+        self.pacer.wait(buffer.len() / channels);
+        Ok(())
+    }
+
+    fn channels(&self) -> usize {
+        self.spec.channels as usize
+    }
+
+    fn rate(&self) -> u32 {
+        self.spec.rate
+    }
+}
+
+// xorshift, plenty for noise and the same sequence every run
+struct NoiseSource {
+    state: u64,
+}
+
+impl NoiseSource {
+    fn new(seed: u64) -> Self {
+        NoiseSource { state: seed }
+    }
+
+    // uniform from -1 to 1
+    fn next(&mut self) -> f64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        (self.state >> 11) as f64 / (1u64 << 52) as f64 - 1.
+    }
+}
+
+#[test]
+fn test_generator_sine_bin() {
+    use data_helpers::peak_index;
+    use super::processor::{AudioProcessor, ProcessorSettings};
+    use super::window::WindowFunction;
+    // 1 kHz with 1024 points at 32768 Hz lands exactly on bin 1000 / 32 Hz
+    let spec = GeneratorSpec {
+        signal: Signal::Sine { frequency: 1000. },
+        amplitude: 0.5,
+        rate: 32768,
+        channels: 1,
+    };
+    let settings = ProcessorSettings {
+        window: WindowFunction::Rectangular,
+        fft_size: 1024,
+        ..ProcessorSettings::default()
+    };
+    let positions = ChannelPosition::pulse_order(1);
+    let mut processor = AudioProcessor::new(0, Box::new(GeneratorBackend::new(&spec)), &positions, &settings);
+    let frame = processor.get_data_frame().unwrap();
+    assert_eq!(peak_index(&frame.magnitudes[0][..513]), 1000 * 1024 / 32768);
+}
+
+#[test]
+fn test_generator_signals() {
+    let spec = |signal| {
+        GeneratorSpec {
+            signal: signal,
+            amplitude: 1.,
+            rate: 1000,
+            channels: 2,
+        }
+    };
+    let mut impulses = GeneratorBackend::new(&spec(Signal::ImpulseTrain { frequency: 100. }));
+    let samples: Vec<f64> = (0..30).map(|_| impulses.next_sample()).collect();
+    assert_eq!(samples.iter().filter(|&&s| s == 1.).count(), 3);
+    assert_eq!(samples[10], 1.);
+    let mut noise = GeneratorBackend::new(&spec(Signal::WhiteNoise));
+    assert!((0..1000).map(|_| noise.next_sample()).all(|s| s >= -1. && s < 1.));
+    let mut pink = GeneratorBackend::new(&spec(Signal::PinkNoise));
+    assert!((0..10000).map(|_| pink.next_sample()).all(|s| s.abs() <= 1.));
+    assert!(generator_source_info(spec(Signal::Metronome { bpm: 0. })).is_err());
+    assert!(generator_source_info(GeneratorSpec { amplitude: 1.5, ..spec(Signal::WhiteNoise) }).is_err());
+}
@@ -0,0 +1,346 @@
+use std::collections::VecDeque;
+use std::f64::consts::PI;
+
+use super::{AudioFrame, ChannelPosition};
+
+// loudness is measured in 100 ms blocks, 4 of them make a gating block, 30 the short term window
+const BLOCK_MS: u32 = 100;
+const GATING_BLOCKS: usize = 4;
+const SHORT_TERM_BLOCKS: usize = 30;
+// gating of the integrated loudness, in LUFS and LU below the ungated loudness
+const ABSOLUTE_GATE: f64 = -70.;
+const RELATIVE_GATE: f64 = 10.;
+// gating blocks are kept as a histogram so the integrated loudness doesn't grow with time
+const HISTOGRAM_MAX: f64 = 5.;
+const HISTOGRAM_STEP: f64 = 0.1;
+// the true peak is taken from the signal upsampled this many times
+const OVERSAMPLING: usize = 4;
+const TAPS_PER_PHASE: usize = 12;
+// the held peak stays put this long, then falls this many dB a second until it meets the signal
+const PEAK_HOLD_SECS: f64 = 2.;
+const PEAK_FALL: f64 = 20.;
+// BS.1770 weight of the rear and side channels, everything else counts once and the LFE not at all
+const SURROUND_GAIN: f64 = 1.41;
+
+// a second order iir filter, transposed direct form 2
+#[derive(Clone)]
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+    z: [f64; 2],
+}
+
+impl Biquad {
+    fn process(&mut self, x: f64) -> f64 {
+        let y = self.b[0] * x + self.z[0];
+        self.z[0] = self.b[1] * x - self.a[0] * y + self.z[1];
+        self.z[1] = self.b[2] * x - self.a[1] * y;
+        y
+    }
+}
+
+// the two stages of the K-weighting from ITU-R BS.1770, worked out for any sample rate
+fn k_weighting(rate: u32) -> [Biquad; 2] {
+    let rate = rate as f64;
+    // high shelf modelling the head
+    let (f0, gain, q) = (1681.974450955533, 3.999843853973347, 0.7071752369554196);
+    let k = (PI * f0 / rate).tan();
+    let vh = 10f64.powf(gain / 20.);
+    let vb = vh.powf(0.4996667741545416);
+    let a0 = 1. + k / q + k * k;
+    let shelf = Biquad {
+        b: [(vh + vb * k / q + k * k) / a0, 2. * (k * k - vh) / a0, (vh - vb * k / q + k * k) / a0],
+        a: [2. * (k * k - 1.) / a0, (1. - k / q + k * k) / a0],
+        z: [0.; 2],
+    };
+    // high pass taking out the lows
+    let (f0, q) = (38.13547087602444, 0.5003270373238773);
+    let k = (PI * f0 / rate).tan();
+    let a0 = 1. + k / q + k * k;
+    let high_pass = Biquad {
+        b: [1., -2., 1.],
+        a: [2. * (k * k - 1.) / a0, (1. - k / q + k * k) / a0],
+        z: [0.; 2],
+    };
+    [shelf, high_pass]
+}
+
+// polyphase windowed sinc interpolation filter, one row of taps per output phase
+fn oversampling_filter() -> Vec<Vec<f64>> {
+    let len = OVERSAMPLING * TAPS_PER_PHASE;
+    let center = (len - 1) as f64 / 2.;
+    let prototype: Vec<f64> = (0..len)
+        .map(|n| {
+            let t = (n as f64 - center) / OVERSAMPLING as f64;
+            let sinc = if t == 0. { 1. } else { (PI * t).sin() / (PI * t) };
+            let window = 0.5 - 0.5 * (2. * PI * (n as f64 + 0.5) / len as f64).cos();
+            sinc * window
+        })
+        .collect();
+    (0..OVERSAMPLING)
+        .map(|phase| {
+            let taps: Vec<f64> = (0..TAPS_PER_PHASE).map(|k| prototype[k * OVERSAMPLING + phase]).collect();
+            // every phase passes dc unchanged
+            let sum = taps.iter().sum::<f64>();
+            taps.iter().map(|t| t / sum).collect()
+        })
+        .collect()
+}
+
+struct ChannelState {
+    // how much the channel counts towards the loudness
+    gain: f64,
+    weighting: [Biquad; 2],
+    // the last TAPS_PER_PHASE samples, newest first
+    delay: VecDeque<f64>,
+    // sum of the squared K-weighted samples of the current block
+    block_sum: f64,
+}
+
+// Measures the level of a source the way broadcast meters do: rms and true peak per channel
+// every frame, plus K-weighted short term and gated integrated loudness in LUFS.
+pub struct LoudnessMeter {
+    channels: Vec<ChannelState>,
+    filter: Vec<Vec<f64>>,
+    block_len: usize,
+    block_pos: usize,
+    // mean square of the finished blocks summed over the channels, newest last
+    blocks: VecDeque<f64>,
+    // number and energy sum of the gating blocks falling in each loudness step
+    histogram: Vec<(u64, f64)>,
+    rate: f64,
+    // highest true peak lately and how many seconds it is held for still
+    peak_hold: f64,
+    hold_left: f64,
+}
+
+impl LoudnessMeter {
+    // positions: where each channel of the source goes
+    pub fn new(positions: &[ChannelPosition], rate: u32) -> Self {
+        let bins = ((HISTOGRAM_MAX - ABSOLUTE_GATE) / HISTOGRAM_STEP).ceil() as usize;
+        LoudnessMeter {
+            channels: positions.iter()
+                .map(|&position| {
+                    ChannelState {
+                        gain: channel_gain(position),
+                        weighting: k_weighting(rate),
+                        delay: vec![0.; TAPS_PER_PHASE].into_iter().collect(),
+                        block_sum: 0.,
+                    }
+                })
+                .collect(),
+            filter: oversampling_filter(),
+            block_len: ((rate * BLOCK_MS / 1000) as usize).max(1),
+            block_pos: 0,
+            blocks: VecDeque::with_capacity(SHORT_TERM_BLOCKS),
+            histogram: vec![(0, 0.); bins],
+            rate: rate as f64,
+            peak_hold: 0.,
+            hold_left: 0.,
+        }
+    }
+
+    // samples: per channel, the samples that are new in this frame as fractions of full scale
+    pub fn process(&mut self, samples: &[Vec<f64>], frame: &mut AudioFrame) {
+        let len = samples.first().map(|s| s.len()).unwrap_or(0);
+        frame.loudness.rms.resize(self.channels.len(), 0.);
+        frame.loudness.true_peak.resize(self.channels.len(), 0.);
+        for (c, channel) in samples.iter().enumerate().take(self.channels.len()) {
+            let mut square_sum = 0.;
+            let mut peak = 0f64;
+            let state = &mut self.channels[c];
+            for &x in channel.iter() {
+                square_sum += x * x;
+                state.delay.pop_back();
+                state.delay.push_front(x);
+                for taps in self.filter.iter() {
+                    let y = taps.iter().zip(state.delay.iter()).map(|(t, s)| t * s).sum::<f64>();
+                    peak = peak.max(y.abs());
+                }
+                peak = peak.max(x.abs());
+            }
+            frame.loudness.rms[c] = (square_sum / len.max(1) as f64).sqrt();
+            frame.loudness.true_peak[c] = peak;
+        }
+        self.hold_peak(len, frame);
+        // the blocks can end anywhere within the frame
+        for i in 0..len {
+            for (state, channel) in self.channels.iter_mut().zip(samples.iter()) {
+                let shelved = state.weighting[0].process(channel[i]);
+                let weighted = state.weighting[1].process(shelved);
+                state.block_sum += weighted * weighted;
+            }
+            self.block_pos += 1;
+            if self.block_pos == self.block_len {
+                self.finish_block();
+            }
+        }
+        frame.loudness.short_term = if self.blocks.len() == SHORT_TERM_BLOCKS {
+            Some(loudness(self.blocks.iter().sum::<f64>() / SHORT_TERM_BLOCKS as f64))
+        } else {
+            None
+        };
+        frame.loudness.integrated = self.integrated();
+    }
+
+    // a single hop is far too short to see a peak in, so meters show the highest one lately
+    fn hold_peak(&mut self, len: usize, frame: &mut AudioFrame) {
+        let peak = frame.loudness.true_peak.iter().fold(0., |a, &b| b.max(a));
+        let secs = len as f64 / self.rate;
+        if peak >= self.peak_hold {
+            self.peak_hold = peak;
+            self.hold_left = PEAK_HOLD_SECS;
+        } else if self.hold_left > 0. {
+            self.hold_left -= secs;
+        } else {
+            self.peak_hold = (self.peak_hold * 10f64.powf(-PEAK_FALL * secs / 20.)).max(peak);
+        }
+        frame.loudness.peak_hold = self.peak_hold;
+    }
+
+    fn finish_block(&mut self) {
+        let block_len = self.block_len as f64;
+        let energy = self.channels.iter().map(|s| s.gain * s.block_sum / block_len).sum::<f64>();
+        for state in self.channels.iter_mut() {
+            state.block_sum = 0.;
+        }
+        self.block_pos = 0;
+        if self.blocks.len() == SHORT_TERM_BLOCKS {
+            self.blocks.pop_front();
+        }
+        self.blocks.push_back(energy);
+        // gating blocks overlap by 75%, one ends with every block
+        if self.blocks.len() >= GATING_BLOCKS {
+            let gating_energy = self.blocks.iter().rev().take(GATING_BLOCKS).sum::<f64>() / GATING_BLOCKS as f64;
+            let level = loudness(gating_energy);
+            if level > ABSOLUTE_GATE {
+                let bin = (((level - ABSOLUTE_GATE) / HISTOGRAM_STEP) as usize).min(self.histogram.len() - 1);
+                self.histogram[bin].0 += 1;
+                self.histogram[bin].1 += gating_energy;
+            }
+        }
+    }
+
+    // blocks within RELATIVE_GATE of the loudness of everything above the absolute gate
+    fn integrated(&self) -> Option<f64> {
+        let (count, energy) = self.histogram.iter().fold((0, 0.), |acc, bin| (acc.0 + bin.0, acc.1 + bin.1));
+        if count == 0 {
+            return None;
+        }
+        let threshold = loudness(energy / count as f64) - RELATIVE_GATE;
+        let (count, energy) = self.histogram
+            .iter()
+            .enumerate()
+            .filter(|&(i, _)| ABSOLUTE_GATE + (i as f64 + 0.5) * HISTOGRAM_STEP >= threshold)
+            .fold((0, 0.), |acc, (_, bin)| (acc.0 + bin.0, acc.1 + bin.1));
+        if count == 0 {
+            None
+        } else {
+            Some(loudness(energy / count as f64))
+        }
+    }
+}
+
+fn channel_gain(position: ChannelPosition) -> f64 {
+    match position {
+        ChannelPosition::Lfe => 0.,
+        ChannelPosition::RearLeft |
+        ChannelPosition::RearRight |
+        ChannelPosition::SideLeft |
+        ChannelPosition::SideRight => SURROUND_GAIN,
+        _ => 1.,
+    }
+}
+
+// LUFS of a mean square summed over the channels
+fn loudness(energy: f64) -> f64 {
+    -0.691 + 10. * energy.max(1e-20).log10()
+}
+
+#[test]
+fn test_meter_sine() {
+    // the K-weighting is made so a 997 Hz sine reads as its mean square, -9 LUFS at half scale
+    let rate = 48000;
+    let mut meter = LoudnessMeter::new(&ChannelPosition::pulse_order(1), rate);
+    let mut frame = AudioFrame::default();
+    for block in 0..400 {
+        let samples: Vec<f64> = (0..480)
+            .map(|i| {
+                let t = (block * 480 + i) as f64 / rate as f64;
+                0.5 * (2. * PI * 997. * t).sin()
+            })
+            .collect();
+        meter.process(&[samples], &mut frame);
+    }
+    let expected = 10. * 0.125f64.log10();
+    assert!((frame.loudness.rms[0] - 0.5 / 2f64.sqrt()).abs() < 0.01);
+    assert!((frame.loudness.true_peak[0] - 0.5).abs() < 0.01);
+    assert!((frame.loudness.short_term.unwrap() - expected).abs() < 0.2);
+    assert!((frame.loudness.integrated.unwrap() - expected).abs() < 0.2);
+}
+
+#[test]
+fn test_meter_surround_weights() {
+    // front left, rear left, center, front right, rear right, lfe
+    let positions = ChannelPosition::pulse_order(6);
+    let rate = 48000;
+    let level_of = |channel: usize| {
+        let mut meter = LoudnessMeter::new(&positions, rate);
+        let mut frame = AudioFrame::default();
+        for block in 0..40 {
+            let mut samples = vec![vec![0.; 4800]; 6];
+            for (i, sample) in samples[channel].iter_mut().enumerate() {
+                let t = (block * 4800 + i) as f64 / rate as f64;
+                *sample = 0.5 * (2. * PI * 997. * t).sin();
+            }
+            meter.process(&samples, &mut frame);
+        }
+        frame.loudness
+    };
+    let front = level_of(0).short_term.unwrap();
+    let rear = level_of(1).short_term.unwrap();
+    assert!((rear - front - 10. * SURROUND_GAIN.log10()).abs() < 0.01);
+    // the lfe alone reads as silence
+    let lfe = level_of(5);
+    assert!(lfe.short_term.unwrap() < ABSOLUTE_GATE);
+    assert_eq!(lfe.integrated, None);
+    assert!(lfe.rms[5] > 0.3);
+}
+
+#[test]
+fn test_meter_silence() {
+    let mut meter = LoudnessMeter::new(&ChannelPosition::pulse_order(2), 48000);
+    let mut frame = AudioFrame::default();
+    let silence = vec![vec![0.; 4800]; 2];
+    for _ in 0..40 {
+        meter.process(&silence, &mut frame);
+    }
+    assert_eq!(frame.loudness.rms, vec![0., 0.]);
+    // below the absolute gate
+    assert_eq!(frame.loudness.integrated, None);
+}
+
+#[test]
+fn test_meter_peak_hold() {
+    let mut meter = LoudnessMeter::new(&ChannelPosition::pulse_order(1), 1000);
+    let mut frame = AudioFrame::default();
+    let mut click = vec![vec![0.; 10]];
+    click[0][5] = 0.5;
+    meter.process(&click, &mut frame);
+    let silence = vec![vec![0.; 10]];
+    // held for 2 s of 10 ms hops
+    for _ in 0..150 {
+        meter.process(&silence, &mut frame);
+    }
+    assert_eq!(frame.loudness.true_peak, vec![0.]);
+    assert_eq!(frame.loudness.peak_hold, 0.5);
+    // then falls by 20 dB a second
+    for _ in 0..150 {
+        meter.process(&silence, &mut frame);
+    }
+    assert!(frame.loudness.peak_hold < 0.5 && frame.loudness.peak_hold > 0.01);
+    for _ in 0..200 {
+        meter.process(&silence, &mut frame);
+    }
+    assert!(frame.loudness.peak_hold < 0.05);
+}
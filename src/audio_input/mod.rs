@@ -1,11 +1,28 @@
+mod backend;
 mod definitions;
+mod file;
+mod generator;
+mod meter;
+mod onset;
+mod pipe;
 mod processor;
+mod recording;
 mod sources;
+mod tempo;
 mod updater;
+mod window;
+mod worker;
 
-pub use self::definitions::{AudioFrame, FRAMES};
-pub use self::sources::{get_sources, PaSourceInfo};
+pub use self::backend::{CaptureBackend, open_backend};
+pub use self::definitions::{find_positions, AudioFrame, BandEnergies, ChannelLayout, ChannelPosition, FrameContent, FrameSummary, Loudness, DEFAULT_FFT_SIZE, MIN_FFT_SIZE, MAX_FFT_SIZE};
+pub use self::file::probe_file;
+pub use self::generator::{generator_source_info, GeneratorSpec, Signal};
+pub use self::onset::OnsetSettings;
+pub use self::pipe::{pipe_source_info, PcmFormat, PipeSpec};
+pub use self::recording::{probe_recording, FrameReader, FrameWriter, SharedRecorder};
+pub use self::sources::{get_sources, watch_sources, PaSourceInfo, SourceEvent, SourceKind};
 pub use self::updater::AudioUpdater;
+pub use self::window::WindowFunction;
 
 // NOTE: temporary placeholder for main
-pub use self::processor::AudioProcessor;
+pub use self::processor::{AudioProcessor, ProcessorSettings, ProcessorSettingsTable};
@@ -0,0 +1,179 @@
+use std::collections::VecDeque;
+
+use super::AudioFrame;
+
+// upper edges of the bass and mid ranges in Hz, treble is everything above
+const BASS_MAX_HZ: f64 = 250.;
+const MID_MAX_HZ: f64 = 4000.;
+
+// how beats get picked out of the onset strength
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct OnsetSettings {
+    // how far above the recent average (in standard deviations) the flux has to go
+    #[serde(default = "default_sensitivity")]
+    pub sensitivity: f64,
+    // how much history the threshold adapts to
+    #[serde(default = "default_window_ms")]
+    pub window_ms: u64,
+    // beats closer together than this are counted as one
+    #[serde(default = "default_min_interval_ms")]
+    pub min_interval_ms: u64,
+}
+
+fn default_sensitivity() -> f64 {
+    1.5
+}
+
+fn default_window_ms() -> u64 {
+    1000
+}
+
+fn default_min_interval_ms() -> u64 {
+    200
+}
+
+impl Default for OnsetSettings {
+    fn default() -> Self {
+        OnsetSettings {
+            sensitivity: default_sensitivity(),
+            window_ms: default_window_ms(),
+            min_interval_ms: default_min_interval_ms(),
+        }
+    }
+}
+
+// Spectral flux onset detection: the strength is how much the spectrum grew since the last
+// frame, a beat is a peak in it that rises far enough above its own recent average.
+pub struct OnsetDetector {
+    sensitivity: f64,
+    // flux of the frames within the window, newest last
+    history: VecDeque<f64>,
+    window_frames: usize,
+    min_interval_ns: u64,
+    // log magnitudes of the last frame, averaged over the channels
+    previous: Vec<f64>,
+    current: Vec<f64>,
+    last_flux: f64,
+    beats: u64,
+    last_beat: Option<u64>,
+}
+
+impl OnsetDetector {
+    // frame_ns: time between two frames
+    pub fn new(settings: &OnsetSettings, frame_ns: u64) -> Self {
+        let window_ns = settings.window_ms * 1_000_000;
+        let window_frames = (window_ns / frame_ns.max(1)).max(2) as usize;
+        OnsetDetector {
+            sensitivity: settings.sensitivity,
+            history: VecDeque::with_capacity(window_frames),
+            window_frames: window_frames,
+            min_interval_ns: settings.min_interval_ms * 1_000_000,
+            previous: Vec::new(),
+            current: Vec::new(),
+            last_flux: 0.,
+            beats: 0,
+            last_beat: None,
+        }
+    }
+
+    // fills in the onset and band energy fields of a frame whose spectrum is already there
+    pub fn process(&mut self, frame: &mut AudioFrame) {
+        let bins = match frame.magnitudes.first() {
+            Some(channel) => channel.len() / 2,
+            None => return,
+        };
+        // same scale as spectrum::to_full_scale
+        let full_scale = (::std::i16::MAX as f64 + 1.) * bins as f64;
+        let channels = frame.magnitudes.len() as f64;
+        self.current.clear();
+        self.current.resize(bins, 0.);
+        let mut energies = [0f64; 3];
+        let mut counts = [0usize; 3];
+        for i in 0..bins {
+            let magnitude = frame.magnitudes.iter().map(|c| c[i]).sum::<f64>() / (channels * full_scale);
+            let frequency = i as f64 * frame.bin_width;
+            let band = if frequency < BASS_MAX_HZ {
+                0
+            } else if frequency < MID_MAX_HZ {
+                1
+            } else {
+                2
+            };
+            energies[band] += magnitude * magnitude;
+            counts[band] += 1;
+            // compressed so quiet parts of the spectrum count too
+            self.current[i] = (1. + 1000. * magnitude).ln();
+        }
+        let rms = |band: usize| if counts[band] == 0 {
+            0.
+        } else {
+            (energies[band] / counts[band] as f64).sqrt()
+        };
+        frame.energies.bass = rms(0);
+        frame.energies.mid = rms(1);
+        frame.energies.treble = rms(2);
+
+        // only growth counts, fading out isn't an onset
+        let flux = if self.previous.len() == bins {
+            self.current
+                .iter()
+                .zip(self.previous.iter())
+                .map(|(c, p)| (c - p).max(0.))
+                .sum::<f64>() / bins as f64
+        } else {
+            0.
+        };
+        ::std::mem::swap(&mut self.current, &mut self.previous);
+
+        // adaptive threshold over the flux before this frame
+        let count = self.history.len() as f64;
+        let mean = self.history.iter().sum::<f64>() / count.max(1.);
+        let variance = self.history.iter().map(|f| (f - mean) * (f - mean)).sum::<f64>() / count.max(1.);
+        let threshold = mean + self.sensitivity * variance.sqrt();
+        let rested = match self.last_beat {
+            Some(last) => frame.timestamp.saturating_sub(last) >= self.min_interval_ns,
+            None => true,
+        };
+        // wait for a full window before trusting the threshold
+        let beat = self.history.len() == self.window_frames && flux > threshold &&
+                   flux >= self.last_flux && rested;
+        if beat {
+            self.beats += 1;
+            self.last_beat = Some(frame.timestamp);
+        }
+        if self.history.len() == self.window_frames {
+            self.history.pop_front();
+        }
+        self.history.push_back(flux);
+        self.last_flux = flux;
+
+        frame.onset_strength = flux;
+        frame.beat = beat;
+        frame.beats = self.beats;
+        frame.last_beat = self.last_beat;
+    }
+}
+
+#[test]
+fn test_onset_detects_bursts() {
+    use super::ChannelLayout;
+    let mut detector = OnsetDetector::new(&OnsetSettings::default(), 10_000_000);
+    let mut beats = Vec::new();
+    // a loud burst every 50 frames (500 ms) over quiet noise
+    for n in 0..400u64 {
+        let level = if n % 50 == 0 { 1_000_000. } else { 1000. + (n % 7) as f64 * 100. };
+        let mut frame = AudioFrame {
+            magnitudes: vec![vec![level; 256]],
+            bin_width: 44100. / 256.,
+            layout: ChannelLayout::Mono,
+            timestamp: n * 10_000_000,
+            ..AudioFrame::default()
+        };
+        detector.process(&mut frame);
+        if frame.beat {
+            beats.push(n);
+        }
+    }
+    // nothing before the threshold has a full window, every burst after it
+    assert_eq!(beats, vec![100, 150, 200, 250, 300, 350]);
+}
@@ -0,0 +1,161 @@
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::os::unix::fs::FileTypeExt;
+use std::path::{Path, PathBuf};
+
+use byteorder::{ByteOrder, LittleEndian};
+
+use super::{ChannelPosition, PaSourceInfo, SourceKind};
+use super::backend::{CaptureBackend, float_to_i16};
+
+// the path that stands for standard input instead of a fifo
+pub const STDIN_PATH: &'static str = "-";
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum PcmFormat {
+    S16Le,
+    F32Le,
+}
+
+impl PcmFormat {
+    fn bytes_per_sample(&self) -> usize {
+        match *self {
+            PcmFormat::S16Le => 2,
+            PcmFormat::F32Le => 4,
+        }
+    }
+}
+
+// Raw interleaved pcm with no header, so everything about the stream has to be configured.
+// This is what mpd's and snapcast's fifo outputs write.
+#[derive(Clone, Debug)]
+pub struct PipeSpec {
+    pub path: PathBuf,
+    pub format: PcmFormat,
+    pub rate: u32,
+    pub channels: u8,
+}
+
+impl PipeSpec {
+    fn is_stdin(&self) -> bool {
+        self.path == Path::new(STDIN_PATH)
+    }
+
+    fn is_fifo(&self) -> bool {
+        fs::metadata(&self.path).map(|m| m.file_type().is_fifo()).unwrap_or(false)
+    }
+}
+
+pub fn pipe_source_info(spec: PipeSpec) -> PaSourceInfo {
+    let (name, description) = if spec.is_stdin() {
+        ("pipe:stdin".to_string(), "PCM from standard input".to_string())
+    } else {
+        (format!("pipe:{}", spec.path.display()), format!("PCM pipe {}", spec.path.display()))
+    };
+    PaSourceInfo {
+        name: name,
+        description: description,
+        rate: spec.rate,
+        channels: spec.channels,
+        positions: ChannelPosition::file_order(spec.channels as usize),
+        kind: SourceKind::Pipe(spec),
+    }
+}
+
+pub struct PipeBackend {
+    spec: PipeSpec,
+    // opened on the first read: opening a fifo blocks until something starts writing to it
+    input: Option<Box<Read>>,
+    byte_buffer: Vec<u8>,
+}
+
+impl PipeBackend {
+    pub fn new(spec: &PipeSpec) -> PipeBackend {
+        PipeBackend {
+            spec: spec.clone(),
+            input: None,
+            byte_buffer: Vec::new(),
+        }
+    }
+
+    fn open(&self) -> Result<Box<Read>, String> {
+        if self.spec.is_stdin() {
+            Ok(Box::new(io::stdin()))
+        } else {
+            match File::open(&self.spec.path) {
+                Ok(f) => Ok(Box::new(f)),
+                Err(e) => Err(format!("Could not open {}: {}", self.spec.path.display(), e)),
+            }
+        }
+    }
+
+    fn fill_byte_buffer(&mut self) -> Result<(), String> {
+        loop {
+            if self.input.is_none() {
+                self.input = Some(try!(self.open()));
+            }
+            let result = self.input.as_mut().unwrap().read_exact(self.byte_buffer.as_mut_slice());
+            match result {
+                Ok(()) => return Ok(()),
+                // the writer went away (e.g. mpd was paused), wait for the next one.
+                // a regular file would just end again right away
+                Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof && self.spec.is_fifo() => {
+                    self.input = None;
+                }
+                Err(e) => return Err(format!("Could not read from {}: {}", self.spec.path.display(), e)),
+            }
+        }
+    }
+}
+
+impl CaptureBackend for PipeBackend {
+    fn read(&mut self, buffer: &mut [i16]) -> Result<(), String> {
+        let bytes_per_sample = self.spec.format.bytes_per_sample();
+        self.byte_buffer.resize(buffer.len() * bytes_per_sample, 0);
+        try!(self.fill_byte_buffer());
+        let chunks = self.byte_buffer.chunks(bytes_per_sample);
+        for (sample, bytes) in buffer.iter_mut().zip(chunks) {
+            *sample = match self.spec.format {
+                PcmFormat::S16Le => LittleEndian::read_i16(bytes),
+                PcmFormat::F32Le => float_to_i16(LittleEndian::read_f32(bytes)),
+            };
+        }
+        Ok(())
+    }
+
+    fn channels(&self) -> usize {
+        self.spec.channels as usize
+    }
+
+    fn rate(&self) -> u32 {
+        self.spec.rate
+    }
+}
+
+#[test]
+fn test_pipe_formats() {
+    use std::io::Write;
+
+    let path = ::std::env::temp_dir().join("visualizers_test_pipe_formats.pcm");
+    let mut spec = PipeSpec {
+        path: path.clone(),
+        format: PcmFormat::S16Le,
+        rate: 48000,
+        channels: 2,
+    };
+    File::create(&path).unwrap().write_all(&[0x01, 0x00, 0xff, 0xff, 0x00, 0x80, 0xff, 0x7f]).unwrap();
+    let mut buffer = vec![0; 4];
+    let mut backend = PipeBackend::new(&spec);
+    backend.read(&mut buffer).unwrap();
+    assert_eq!(buffer.as_slice(), &[1, -1, -32768, 32767]);
+    // not a fifo, so the end of the file is an error instead of a reopen
+    assert!(backend.read(&mut buffer).is_err());
+
+    spec.format = PcmFormat::F32Le;
+    // 0.5 and -1.0
+    File::create(&path).unwrap().write_all(&[0x00, 0x00, 0x00, 0x3f, 0x00, 0x00, 0x80, 0xbf]).unwrap();
+    let mut buffer = vec![0; 2];
+    PipeBackend::new(&spec).read(&mut buffer).unwrap();
+    assert_eq!(buffer.as_slice(), &[16383, -32767]);
+    ::std::fs::remove_file(&path).unwrap();
+}
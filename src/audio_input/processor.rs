@@ -1,80 +1,485 @@
 use dft;
 use dft::{Operation, Plan};
-use pa_simple::{Builder, Reader};
 
-use super::{AudioFrame, FRAMES, PaSourceInfo};
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use super::{AudioFrame, ChannelLayout, ChannelPosition, DEFAULT_FFT_SIZE, MIN_FFT_SIZE, MAX_FFT_SIZE};
+use super::definitions::timestamp_now;
+use super::backend::CaptureBackend;
+use super::meter::LoudnessMeter;
+use super::onset::{OnsetDetector, OnsetSettings};
+use super::tempo::TempoTracker;
+use super::window::WindowFunction;
+
+// how a single source gets analysed
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ProcessorSettings {
+    #[serde(default)]
+    pub window: WindowFunction,
+    // number of samples per channel that go into each transform
+    #[serde(default = "default_fft_size")]
+    pub fft_size: usize,
+    // number of new samples per channel read between transforms,
+    // anything less than fft_size makes the analysis frames overlap. none is the same as fft_size
+    #[serde(default)]
+    pub hop_size: Option<usize>,
+    // the waveform keeps every nth sample (averaged over the n), 1 keeps all of them
+    #[serde(default = "default_waveform_decimation")]
+    pub waveform_decimation: usize,
+    #[serde(default)]
+    pub onset: OnsetSettings,
+    // write every analysis frame of the source to this file, to be replayed later.
+    // only for sources in per_source, the file starts over once per run of the program
+    #[serde(default)]
+    pub record: Option<PathBuf>,
+}
+
+fn default_fft_size() -> usize {
+    DEFAULT_FFT_SIZE
+}
+
+fn default_waveform_decimation() -> usize {
+    1
+}
+
+impl Default for ProcessorSettings {
+    fn default() -> Self {
+        ProcessorSettings {
+            window: WindowFunction::default(),
+            fft_size: DEFAULT_FFT_SIZE,
+            hop_size: None,
+            waveform_decimation: 1,
+            onset: OnsetSettings::default(),
+            record: None,
+        }
+    }
+}
+
+impl ProcessorSettings {
+    // fft_size rounded up to a power of 2 within the supported range
+    pub fn fft_size(&self) -> usize {
+        let size = self.fft_size.next_power_of_two();
+        if size < MIN_FFT_SIZE {
+            MIN_FFT_SIZE
+        } else if size > MAX_FFT_SIZE {
+            MAX_FFT_SIZE
+        } else {
+            size
+        }
+    }
+
+    pub fn hop_size(&self) -> usize {
+        let fft_size = self.fft_size();
+        match self.hop_size {
+            None => fft_size,
+            Some(0) => 1,
+            Some(hop_size) => ::std::cmp::min(hop_size, fft_size),
+        }
+    }
+}
+
+// processor settings for every source, keyed by source name
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ProcessorSettingsTable {
+    #[serde(default)]
+    pub default: ProcessorSettings,
+    #[serde(default)]
+    pub per_source: HashMap<String, ProcessorSettings>,
+}
+
+impl Default for ProcessorSettingsTable {
+    fn default() -> Self {
+        ProcessorSettingsTable {
+            default: ProcessorSettings::default(),
+            per_source: HashMap::new(),
+        }
+    }
+}
+
+impl ProcessorSettingsTable {
+    pub fn for_source(&self, name: &str) -> &ProcessorSettings {
+        self.per_source.get(name).unwrap_or(&self.default)
+    }
+
+    // every source would write to the same file otherwise
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(ref path) = self.default.record {
+            return Err(format!("Recording to {} has to be set for a single source in per_source",
+                               path.display()));
+        }
+        let mut paths: Vec<_> = self.per_source.values().filter_map(|s| s.record.as_ref()).collect();
+        paths.sort();
+        if let Some(pair) = paths.windows(2).find(|pair| pair[0] == pair[1]) {
+            return Err(format!("More than one source records to {}", pair[0].display()));
+        }
+        Ok(())
+    }
+}
 
 pub struct AudioProcessor {
     source_index: usize,
     channels: usize,
     rate: u32,
+    fft_size: usize,
+    hop_size: usize,
     dft_plan: Plan<f64>,
-    recorder: Reader<i16>,
+    window: Vec<f64>,
+    backend: Box<CaptureBackend>,
     audio_buffer: Vec<i16>,
+    // the last fft_size samples of each channel, oldest first
+    history: Vec<Vec<f64>>,
     secondary_buffers: Vec<Vec<f64>>,
-    previous: Vec<Vec<f64>>,
+    mid_side_buffers: Vec<Vec<f64>>,
+    // frames produced so far
+    sequence: u64,
+    // whether frames carry the waveform and mid/side spectra too, the latter from this
+    // left and right channel
+    waveform: bool,
+    mid_side: Option<(usize, usize)>,
+    waveform_decimation: usize,
+    onset: OnsetDetector,
+    tempo: TempoTracker,
+    meter: LoudnessMeter,
+    // the new samples of each channel as fractions of full scale, for the meter
+    meter_buffers: Vec<Vec<f64>>,
 }
 
-// needed to allow them to be used in a vector in a separate thread
-unsafe impl Send for AudioProcessor {}
-
 impl AudioProcessor {
-    pub fn new(sources: &[Option<PaSourceInfo>], source_index: usize) -> Option<AudioProcessor> {
-        // temporary
-        match sources[source_index] {
-            Some(ref source) => {
-                Some(AudioProcessor {
-                    source_index: source_index,
-                    channels: source.channels as usize,
-                    rate: source.rate,
-                    dft_plan: Plan::new(Operation::Forward, FRAMES),
-                    recorder: Builder::new("visualizers", "visualizers")
-                        .channels(source.channels)
-                        .rate(source.rate)
-                        .device(&source.name)
-                        .reader_i16(),
-                    audio_buffer: vec![0; FRAMES * source.channels as usize],
-                    secondary_buffers: vec![vec![0f64; FRAMES]; source.channels as usize],
-                    previous: vec![vec![0f64; FRAMES]; source.channels as usize],
-                })
-            }
-            None => None,
+    // positions: where each channel of the source goes
+    pub fn new(source_index: usize,
+               backend: Box<CaptureBackend>,
+               positions: &[ChannelPosition],
+               settings: &ProcessorSettings)
+               -> AudioProcessor {
+        let channels = backend.channels();
+        // the backend has the final say on the channel count
+        let positions = if positions.len() == channels {
+            positions.to_vec()
+        } else {
+            ChannelPosition::pulse_order(channels)
+        };
+        let fft_size = settings.fft_size();
+        let hop_size = settings.hop_size();
+        let rate = backend.rate();
+        let frame_ns = hop_size as u64 * 1_000_000_000 / rate.max(1) as u64;
+        AudioProcessor {
+            source_index: source_index,
+            channels: channels,
+            rate: rate,
+            fft_size: fft_size,
+            hop_size: hop_size,
+            dft_plan: Plan::new(Operation::Forward, fft_size),
+            window: settings.window.coefficients(fft_size),
+            backend: backend,
+            audio_buffer: vec![0; hop_size * channels],
+            history: vec![vec![0f64; fft_size]; channels],
+            secondary_buffers: vec![vec![0f64; fft_size]; channels],
+            mid_side_buffers: vec![vec![0f64; fft_size]; 2],
+            sequence: 0,
+            waveform: false,
+            mid_side: None,
+            waveform_decimation: settings.waveform_decimation.max(1),
+            onset: OnsetDetector::new(&settings.onset, frame_ns),
+            tempo: TempoTracker::new(frame_ns),
+            meter: LoudnessMeter::new(&positions, rate),
+            meter_buffers: vec![vec![0f64; hop_size]; channels],
         }
+    }
+
+    pub fn set_waveform(&mut self, waveform: bool) {
+        self.waveform = waveform;
+    }
+
+    // the left and right channel to take the mid/side of, None for no mid/side
+    pub fn set_mid_side(&mut self, left_right: Option<(usize, usize)>) {
+        self.mid_side = match left_right {
+            Some((left, right)) if left < self.channels && right < self.channels => Some((left, right)),
+            _ => None,
+        };
+    }
 
+    // a silent frame with the right shape, for before the first reading
+    pub fn empty_frame(&self) -> AudioFrame {
+        AudioFrame {
+            magnitudes: vec![vec![0f64; self.fft_size]; self.channels],
+            samples: None,
+            rate: self.rate,
+            bin_width: self.rate as f64 / self.fft_size as f64,
+            layout: ChannelLayout::from_channels(self.channels),
+            sequence: self.sequence,
+            timestamp: timestamp_now(),
+            ..AudioFrame::default()
+        }
     }
 
-    // NOTE: change this to allow an input of &mut [Vec<f64>] to prevent realloc?
     // get partially processed data from 1 reading
     // raw audio data -> fourier transform -> magnitude -> scale by impulse vec
-    pub fn get_data_frame(&mut self) -> AudioFrame {
-        self.recorder.read(self.audio_buffer.as_mut_slice());
-        // cast to f64
-        for frame_n in 0..FRAMES {
-            let frame_idx = frame_n * self.channels;
-            for channel_offset in 0..self.channels {
-                let orig_idx = frame_idx + channel_offset;
-                self.secondary_buffers[channel_offset][frame_n] =
-                    self.audio_buffer[orig_idx] as f64;
+    pub fn get_data_frame(&mut self) -> Result<AudioFrame, String> {
+        let mut frame = AudioFrame::default();
+        try!(self.get_data_frame_into(&mut frame));
+        Ok(frame)
+    }
+
+    // same as get_data_frame, but reuses frame's allocations once it has the right shape
+    pub fn get_data_frame_into(&mut self, frame: &mut AudioFrame) -> Result<(), String> {
+        try!(self.backend.read(self.audio_buffer.as_mut_slice()));
+        let timestamp = timestamp_now();
+        // slide the new samples into the history as f64
+        let kept = self.fft_size - self.hop_size;
+        let full_scale = ::std::i16::MAX as f64 + 1.;
+        for (channel_offset, (history, meter_buffer)) in self.history
+            .iter_mut()
+            .zip(self.meter_buffers.iter_mut())
+            .enumerate() {
+            for i in 0..kept {
+                history[i] = history[i + self.hop_size];
+            }
+            for frame_n in 0..self.hop_size {
+                let orig_idx = frame_n * self.channels + channel_offset;
+                history[kept + frame_n] = self.audio_buffer[orig_idx] as f64;
+                meter_buffer[frame_n] = self.audio_buffer[orig_idx] as f64 / full_scale;
+            }
+        }
+        // apply the window
+        for (buf, history) in self.secondary_buffers.iter_mut().zip(self.history.iter()) {
+            for i in 0..self.fft_size {
+                buf[i] = history[i] * self.window[i];
             }
         }
-        let mut out_data = Vec::with_capacity(self.channels);
-        for buf in self.secondary_buffers.iter_mut() {
+        frame.magnitudes.resize(self.channels, Vec::new());
+        for (buf, out) in self.secondary_buffers.iter_mut().zip(frame.magnitudes.iter_mut()) {
             // perform fourier transform on each channel
-            // println!("{:?}", buf);
             dft::transform(buf.as_mut_slice(), &self.dft_plan);
-            // println!("{:?}", buf);
-            // unpack
-            out_data.push(dft::unpack(buf).iter().map(|ref c| c.norm_sqr().sqrt()).collect::<Vec<_>>());
+            unpack_magnitudes(buf, out);
         }
-
-        out_data
+        if let Some((left, right)) = self.mid_side {
+            let (mid, side) = self.mid_side_buffers.split_at_mut(1);
+            for i in 0..self.fft_size {
+                let (left, right) = (self.history[left][i], self.history[right][i]);
+                mid[0][i] = (left + right) / 2. * self.window[i];
+                side[0][i] = (left - right) / 2. * self.window[i];
+            }
+            let magnitudes = frame.mid_side.get_or_insert_with(Vec::new);
+            magnitudes.resize(2, Vec::new());
+            for (buf, out) in self.mid_side_buffers.iter_mut().zip(magnitudes.iter_mut()) {
+                dft::transform(buf.as_mut_slice(), &self.dft_plan);
+                unpack_magnitudes(buf, out);
+            }
+        } else {
+            frame.mid_side = None;
+        }
+        if self.waveform {
+            let samples = frame.samples.get_or_insert_with(Vec::new);
+            samples.resize(self.channels, Vec::new());
+            for (out, history) in samples.iter_mut().zip(self.history.iter()) {
+                decimate(history, self.waveform_decimation, out);
+            }
+        } else {
+            frame.samples = None;
+        }
+        self.sequence += 1;
+        frame.rate = self.rate;
+        frame.bin_width = self.rate as f64 / self.fft_size as f64;
+        frame.layout = ChannelLayout::from_channels(self.channels);
+        frame.sequence = self.sequence;
+        frame.timestamp = timestamp;
+        self.onset.process(frame);
+        self.tempo.process(frame);
+        self.meter.process(&self.meter_buffers, frame);
+        Ok(())
     }
 
     pub fn channels(&self) -> usize {
         self.channels
     }
 
+    pub fn rate(&self) -> u32 {
+        self.rate
+    }
+
+    pub fn fft_size(&self) -> usize {
+        self.fft_size
+    }
+
     pub fn source_index(&self) -> usize {
         self.source_index
     }
 }
+
+// magnitudes of the full (mirrored) spectrum straight from dft's packed output,
+// the same as dft::unpack followed by norm() but without the intermediate vec.
+// packed: dc, nyquist, then re/im pairs for the bins in between
+fn unpack_magnitudes(packed: &[f64], out: &mut Vec<f64>) {
+    let n = packed.len();
+    out.resize(n, 0.);
+    out[0] = packed[0].abs();
+    out[n / 2] = packed[1].abs();
+    for k in 1..n / 2 {
+        let magnitude = packed[2 * k].hypot(packed[2 * k + 1]);
+        out[k] = magnitude;
+        out[n - k] = magnitude;
+    }
+}
+
+// average every factor samples into one, scaled to fractions of full scale
+fn decimate(samples: &[f64], factor: usize, out: &mut Vec<f64>) {
+    let full_scale = ::std::i16::MAX as f64 + 1.;
+    out.clear();
+    for chunk in samples.chunks(factor) {
+        out.push(chunk.iter().sum::<f64>() / (chunk.len() as f64 * full_scale));
+    }
+}
+
+// backend that repeats a fixed block of frames, lets the processor run without pulseaudio
+#[cfg(test)]
+struct RepeatingBackend {
+    channels: usize,
+    frames: Vec<i16>,
+}
+
+#[cfg(test)]
+impl CaptureBackend for RepeatingBackend {
+    fn read(&mut self, buffer: &mut [i16]) -> Result<(), String> {
+        for (i, sample) in buffer.iter_mut().enumerate() {
+            *sample = self.frames[i % self.frames.len()];
+        }
+        Ok(())
+    }
+
+    fn channels(&self) -> usize {
+        self.channels
+    }
+
+    fn rate(&self) -> u32 {
+        44100
+    }
+}
+
+#[test]
+fn test_record_only_per_source() {
+    let mut table = ProcessorSettingsTable::default();
+    assert!(table.validate().is_ok());
+    let recording = ProcessorSettings { record: Some(PathBuf::from("a.rec")), ..ProcessorSettings::default() };
+    table.per_source.insert("a".to_string(), recording.clone());
+    assert!(table.validate().is_ok());
+    table.per_source.insert("b".to_string(), recording.clone());
+    assert!(table.validate().is_err());
+    table.per_source.remove("b");
+    table.default = recording;
+    assert!(table.validate().is_err());
+}
+
+#[test]
+fn test_processor_frame_shape() {
+    let backend = RepeatingBackend {
+        channels: 2,
+        frames: vec![1000, -1000],
+    };
+    let mut processor = AudioProcessor::new(3,
+                                            Box::new(backend),
+                                            &ChannelPosition::pulse_order(2),
+                                            &ProcessorSettings::default());
+    let frame = processor.get_data_frame().unwrap();
+    assert_eq!(processor.source_index(), 3);
+    assert_eq!(frame.channels(), 2);
+    assert!(frame.magnitudes.iter().all(|channel| channel.len() == DEFAULT_FFT_SIZE));
+    assert_eq!(frame.layout, ChannelLayout::Stereo);
+    assert_eq!(frame.bin_width, 44100. / DEFAULT_FFT_SIZE as f64);
+    assert_eq!(frame.sequence, 1);
+    assert_eq!(processor.get_data_frame().unwrap().sequence, 2);
+}
+
+#[test]
+fn test_processor_dc_input() {
+    let backend = RepeatingBackend {
+        channels: 1,
+        frames: vec![100],
+    };
+    let settings = ProcessorSettings {
+        window: WindowFunction::Rectangular,
+        ..ProcessorSettings::default()
+    };
+    let mut processor = AudioProcessor::new(0, Box::new(backend), &ChannelPosition::pulse_order(1), &settings);
+    let frame = processor.get_data_frame().unwrap();
+    // all of the energy of a constant signal ends up in the first bin
+    assert_eq!(frame.magnitudes[0][0], 100. * DEFAULT_FFT_SIZE as f64);
+    assert!(frame.magnitudes[0][1..].iter().all(|&m| m < 1e-6));
+}
+
+#[test]
+fn test_hop_size_follows_fft_size() {
+    let mut settings = ProcessorSettings {
+        fft_size: 4096,
+        ..ProcessorSettings::default()
+    };
+    assert_eq!(settings.hop_size(), 4096);
+    settings.hop_size = Some(0);
+    assert_eq!(settings.hop_size(), 1);
+    settings.hop_size = Some(8192);
+    assert_eq!(settings.hop_size(), 4096);
+}
+
+#[test]
+fn test_processor_overlapping_frames() {
+    let backend = RepeatingBackend {
+        channels: 1,
+        frames: vec![100],
+    };
+    let settings = ProcessorSettings {
+        window: WindowFunction::Rectangular,
+        fft_size: 1000,
+        hop_size: Some(512),
+        ..ProcessorSettings::default()
+    };
+    let mut processor = AudioProcessor::new(0, Box::new(backend), &ChannelPosition::pulse_order(1), &settings);
+    assert_eq!(processor.fft_size(), 1024);
+    // only half of the history has been filled after the first read
+    let frame = processor.get_data_frame().unwrap();
+    assert_eq!(frame.magnitudes[0].len(), 1024);
+    assert_eq!(frame.magnitudes[0][0], 100. * 512.);
+    let frame = processor.get_data_frame().unwrap();
+    assert_eq!(frame.magnitudes[0][0], 100. * 1024.);
+}
+
+#[test]
+fn test_processor_waveform() {
+    let backend = RepeatingBackend {
+        channels: 2,
+        frames: vec![16384, 0, -16384, 0],
+    };
+    let settings = ProcessorSettings {
+        waveform_decimation: 2,
+        ..ProcessorSettings::default()
+    };
+    let mut processor = AudioProcessor::new(0, Box::new(backend), &ChannelPosition::pulse_order(2), &settings);
+    assert_eq!(processor.get_data_frame().unwrap().samples, None);
+    processor.set_waveform(true);
+    let frame = processor.get_data_frame().unwrap();
+    let samples = frame.samples.unwrap();
+    assert_eq!(samples.len(), 2);
+    assert_eq!(samples[0].len(), DEFAULT_FFT_SIZE / 2);
+    // each pair of samples averages out
+    assert!(samples[0].iter().all(|&s| s == 0.));
+    processor.set_waveform(false);
+    let plain = processor.get_data_frame().unwrap();
+    assert_eq!(plain.samples, None);
+}
+
+#[test]
+fn test_processor_mid_side() {
+    // the same signal on both channels has no side
+    let backend = RepeatingBackend {
+        channels: 2,
+        frames: vec![1000, 1000, -1000, -1000],
+    };
+    let mut processor = AudioProcessor::new(0,
+                                            Box::new(backend),
+                                            &ChannelPosition::pulse_order(2),
+                                            &ProcessorSettings::default());
+    processor.set_mid_side(Some((0, 1)));
+    let frame = processor.get_data_frame().unwrap();
+    let mid_side = frame.mid_side.unwrap();
+    assert_eq!(mid_side[0], frame.magnitudes[0]);
+    assert!(mid_side[1].iter().all(|&m| m == 0.));
+}
@@ -0,0 +1,334 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter, ErrorKind, Read, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use super::{AudioFrame, ChannelLayout, ChannelPosition, PaSourceInfo, SourceKind};
+
+// start of every recording, the last two bytes are the format version
+const MAGIC: &'static [u8; 8] = b"VISREC01";
+
+// which of the optional parts a recorded frame has
+const HAS_SAMPLES: u8 = 1;
+const HAS_MID_SIDE: u8 = 1 << 1;
+const BEAT: u8 = 1 << 2;
+const HAS_LAST_BEAT: u8 = 1 << 3;
+const HAS_BPM: u8 = 1 << 4;
+const HAS_SHORT_TERM: u8 = 1 << 5;
+const HAS_INTEGRATED: u8 = 1 << 6;
+
+// Writes the analysis frames of one source to a file.
+// Layout, all little endian: the magic, the source's rate (u32), channels (u8) and the
+// position of every channel (u8 each), then one record per frame. Spectra only keep the bins up to the nyquist frequency and everything
+// per bin or per sample is stored as f32, the rest as it is in the frame.
+pub struct FrameWriter<W: Write> {
+    out: W,
+}
+
+impl FrameWriter<BufWriter<File>> {
+    pub fn create(path: &Path, rate: u32, positions: &[ChannelPosition]) -> Result<Self, String> {
+        let file = try!(File::create(path).map_err(|e| format!("{}: {}", path.display(), e)));
+        FrameWriter::new(BufWriter::new(file), rate, positions)
+    }
+}
+
+impl<W: Write> FrameWriter<W> {
+    pub fn new(mut out: W, rate: u32, positions: &[ChannelPosition]) -> Result<Self, String> {
+        try!(out.write_all(MAGIC).map_err(|e| format!("{}", e)));
+        try!(out.write_u32::<LittleEndian>(rate).map_err(|e| format!("{}", e)));
+        try!(out.write_u8(positions.len() as u8).map_err(|e| format!("{}", e)));
+        for position in positions.iter() {
+            try!(out.write_u8(position.code()).map_err(|e| format!("{}", e)));
+        }
+        Ok(FrameWriter { out: out })
+    }
+
+    pub fn write(&mut self, frame: &AudioFrame) -> Result<(), String> {
+        self.write_frame(frame).map_err(|e| format!("Could not record frame: {}", e))
+    }
+
+    fn write_frame(&mut self, frame: &AudioFrame) -> ::std::io::Result<()> {
+        let out = &mut self.out;
+        let mut flags = 0;
+        if frame.samples.is_some() {
+            flags |= HAS_SAMPLES;
+        }
+        if frame.mid_side.is_some() {
+            flags |= HAS_MID_SIDE;
+        }
+        if frame.beat {
+            flags |= BEAT;
+        }
+        if frame.last_beat.is_some() {
+            flags |= HAS_LAST_BEAT;
+        }
+        if frame.bpm.is_some() {
+            flags |= HAS_BPM;
+        }
+        if frame.loudness.short_term.is_some() {
+            flags |= HAS_SHORT_TERM;
+        }
+        if frame.loudness.integrated.is_some() {
+            flags |= HAS_INTEGRATED;
+        }
+        try!(out.write_u64::<LittleEndian>(frame.timestamp));
+        try!(out.write_u64::<LittleEndian>(frame.sequence));
+        try!(out.write_u32::<LittleEndian>(frame.rate));
+        try!(out.write_f64::<LittleEndian>(frame.bin_width));
+        try!(out.write_u8(flags));
+        try!(out.write_u16::<LittleEndian>(frame.magnitudes.len() as u16));
+        try!(out.write_u32::<LittleEndian>(frame.magnitudes.first().map(|c| c.len()).unwrap_or(0) as u32));
+        for channel in frame.magnitudes.iter() {
+            try!(write_half_spectrum(out, channel));
+        }
+        if let Some(ref samples) = frame.samples {
+            try!(out.write_u32::<LittleEndian>(samples.first().map(|c| c.len()).unwrap_or(0) as u32));
+            for channel in samples.iter() {
+                try!(write_f32s(out, channel));
+            }
+        }
+        if let Some(ref mid_side) = frame.mid_side {
+            for channel in mid_side.iter() {
+                try!(write_half_spectrum(out, channel));
+            }
+        }
+        try!(out.write_f64::<LittleEndian>(frame.onset_strength));
+        try!(out.write_u64::<LittleEndian>(frame.beats));
+        if let Some(last_beat) = frame.last_beat {
+            try!(out.write_u64::<LittleEndian>(last_beat));
+        }
+        try!(out.write_f64::<LittleEndian>(frame.energies.bass));
+        try!(out.write_f64::<LittleEndian>(frame.energies.mid));
+        try!(out.write_f64::<LittleEndian>(frame.energies.treble));
+        if let Some(bpm) = frame.bpm {
+            try!(out.write_f64::<LittleEndian>(bpm));
+        }
+        try!(out.write_f64::<LittleEndian>(frame.beat_phase));
+        try!(write_f32s(out, &frame.loudness.rms));
+        try!(write_f32s(out, &frame.loudness.true_peak));
+        try!(out.write_f32::<LittleEndian>(frame.loudness.peak_hold as f32));
+        if let Some(short_term) = frame.loudness.short_term {
+            try!(out.write_f64::<LittleEndian>(short_term));
+        }
+        if let Some(integrated) = frame.loudness.integrated {
+            try!(out.write_f64::<LittleEndian>(integrated));
+        }
+        out.flush()
+    }
+}
+
+// the recording of one source, opened the first time the source gets captured and handed
+// to every worker after that, so capturing again carries on in the same file
+pub type SharedRecorder = Arc<Mutex<FrameWriter<BufWriter<File>>>>;
+
+// Reads the frames back from a recording made by FrameWriter.
+pub struct FrameReader<R: Read> {
+    input: R,
+    rate: u32,
+    positions: Vec<ChannelPosition>,
+}
+
+impl FrameReader<BufReader<File>> {
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let file = try!(File::open(path).map_err(|e| format!("{}: {}", path.display(), e)));
+        FrameReader::new(BufReader::new(file)).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+}
+
+impl<R: Read> FrameReader<R> {
+    pub fn new(mut input: R) -> Result<Self, String> {
+        let mut magic = [0u8; 8];
+        try!(input.read_exact(&mut magic).map_err(|e| format!("{}", e)));
+        if magic[..6] != MAGIC[..6] {
+            return Err("Not a frame recording".to_string());
+        }
+        if &magic != MAGIC {
+            return Err(format!("Unsupported recording version {}, this version reads {}",
+                               String::from_utf8_lossy(&magic[6..]),
+                               String::from_utf8_lossy(&MAGIC[6..])));
+        }
+        let rate = try!(input.read_u32::<LittleEndian>().map_err(|e| format!("{}", e)));
+        let channels = try!(input.read_u8().map_err(|e| format!("{}", e)));
+        let mut positions = Vec::with_capacity(channels as usize);
+        for _ in 0..channels {
+            positions.push(ChannelPosition::from_code(try!(input.read_u8().map_err(|e| format!("{}", e)))));
+        }
+        Ok(FrameReader {
+            input: input,
+            rate: rate,
+            positions: positions,
+        })
+    }
+
+    pub fn rate(&self) -> u32 {
+        self.rate
+    }
+
+    pub fn channels(&self) -> u8 {
+        self.positions.len() as u8
+    }
+
+    pub fn positions(&self) -> &[ChannelPosition] {
+        &self.positions
+    }
+
+    // the next frame, None once the recording has ended
+    pub fn read(&mut self) -> Result<Option<AudioFrame>, String> {
+        let timestamp = match self.input.read_u64::<LittleEndian>() {
+            Ok(timestamp) => timestamp,
+            Err(ref e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(format!("{}", e)),
+        };
+        self.read_frame(timestamp)
+            .map(Some)
+            .map_err(|e| format!("Broken frame in recording: {}", e))
+    }
+
+    fn read_frame(&mut self, timestamp: u64) -> ::std::io::Result<AudioFrame> {
+        let input = &mut self.input;
+        let mut frame = AudioFrame::default();
+        frame.timestamp = timestamp;
+        frame.sequence = try!(input.read_u64::<LittleEndian>());
+        frame.rate = try!(input.read_u32::<LittleEndian>());
+        frame.bin_width = try!(input.read_f64::<LittleEndian>());
+        let flags = try!(input.read_u8());
+        let channels = try!(input.read_u16::<LittleEndian>()) as usize;
+        let bins = try!(input.read_u32::<LittleEndian>()) as usize;
+        for _ in 0..channels {
+            frame.magnitudes.push(try!(read_half_spectrum(input, bins)));
+        }
+        frame.layout = ChannelLayout::from_channels(channels);
+        if flags & HAS_SAMPLES != 0 {
+            let len = try!(input.read_u32::<LittleEndian>()) as usize;
+            let mut samples = Vec::with_capacity(channels);
+            for _ in 0..channels {
+                samples.push(try!(read_f32s(input, len)));
+            }
+            frame.samples = Some(samples);
+        }
+        if flags & HAS_MID_SIDE != 0 {
+            let mid = try!(read_half_spectrum(input, bins));
+            let side = try!(read_half_spectrum(input, bins));
+            frame.mid_side = Some(vec![mid, side]);
+        }
+        frame.onset_strength = try!(input.read_f64::<LittleEndian>());
+        frame.beat = flags & BEAT != 0;
+        frame.beats = try!(input.read_u64::<LittleEndian>());
+        if flags & HAS_LAST_BEAT != 0 {
+            frame.last_beat = Some(try!(input.read_u64::<LittleEndian>()));
+        }
+        frame.energies.bass = try!(input.read_f64::<LittleEndian>());
+        frame.energies.mid = try!(input.read_f64::<LittleEndian>());
+        frame.energies.treble = try!(input.read_f64::<LittleEndian>());
+        if flags & HAS_BPM != 0 {
+            frame.bpm = Some(try!(input.read_f64::<LittleEndian>()));
+        }
+        frame.beat_phase = try!(input.read_f64::<LittleEndian>());
+        frame.loudness.rms = try!(read_f32s(input, channels));
+        frame.loudness.true_peak = try!(read_f32s(input, channels));
+        frame.loudness.peak_hold = try!(input.read_f32::<LittleEndian>()) as f64;
+        if flags & HAS_SHORT_TERM != 0 {
+            frame.loudness.short_term = Some(try!(input.read_f64::<LittleEndian>()));
+        }
+        if flags & HAS_INTEGRATED != 0 {
+            frame.loudness.integrated = Some(try!(input.read_f64::<LittleEndian>()));
+        }
+        Ok(frame)
+    }
+}
+
+// describe a recording as a source, it has the rate and channels of what was recorded
+pub fn probe_recording(path: &Path) -> Result<PaSourceInfo, String> {
+    let reader = try!(FrameReader::open(path));
+    Ok(PaSourceInfo {
+        name: format!("replay:{}", path.display()),
+        description: match path.file_name() {
+            Some(name) => format!("Replay of {}", name.to_string_lossy()),
+            None => format!("Replay of {}", path.display()),
+        },
+        rate: reader.rate(),
+        channels: reader.channels(),
+        positions: reader.positions().to_vec(),
+        kind: SourceKind::Replay(path.to_path_buf()),
+    })
+}
+
+fn write_f32s<W: Write>(out: &mut W, values: &[f64]) -> ::std::io::Result<()> {
+    for &value in values.iter() {
+        try!(out.write_f32::<LittleEndian>(value as f32));
+    }
+    Ok(())
+}
+
+fn read_f32s<R: Read>(input: &mut R, len: usize) -> ::std::io::Result<Vec<f64>> {
+    let mut values = Vec::with_capacity(len);
+    for _ in 0..len {
+        values.push(try!(input.read_f32::<LittleEndian>()) as f64);
+    }
+    Ok(values)
+}
+
+// the other half of a spectrum mirrors the first, so only bins up to the nyquist get stored
+fn write_half_spectrum<W: Write>(out: &mut W, spectrum: &[f64]) -> ::std::io::Result<()> {
+    let half = if spectrum.is_empty() { 0 } else { spectrum.len() / 2 + 1 };
+    write_f32s(out, &spectrum[..half])
+}
+
+fn read_half_spectrum<R: Read>(input: &mut R, bins: usize) -> ::std::io::Result<Vec<f64>> {
+    let half = if bins == 0 { 0 } else { bins / 2 + 1 };
+    let mut spectrum = try!(read_f32s(input, half));
+    for k in half..bins {
+        let mirrored = spectrum[bins - k];
+        spectrum.push(mirrored);
+    }
+    Ok(spectrum)
+}
+
+#[test]
+fn test_recording_round_trip() {
+    use std::io::Cursor;
+    let mut frame = AudioFrame {
+        magnitudes: vec![vec![1., 2., 3., 2.], vec![4., 5., 6., 5.]],
+        samples: Some(vec![vec![0.5, -0.5], vec![0.25, -0.25]]),
+        rate: 44100,
+        bin_width: 11025.,
+        layout: ChannelLayout::Stereo,
+        sequence: 7,
+        timestamp: 123456789,
+        beat: true,
+        beats: 3,
+        last_beat: Some(123456789),
+        bpm: Some(120.),
+        ..AudioFrame::default()
+    };
+    frame.loudness.rms = vec![0.5, 0.25];
+    frame.loudness.true_peak = vec![0.75, 0.5];
+    frame.loudness.peak_hold = 0.75;
+    frame.loudness.integrated = Some(-14.);
+    let mut buffer = Vec::new();
+    {
+        let mut writer = FrameWriter::new(&mut buffer, 44100, &ChannelPosition::pulse_order(2)).unwrap();
+        writer.write(&frame).unwrap();
+        writer.write(&AudioFrame { sequence: 8, ..frame.clone() }).unwrap();
+    }
+    let mut reader = FrameReader::new(Cursor::new(buffer)).unwrap();
+    assert_eq!((reader.rate(), reader.channels()), (44100, 2));
+    assert_eq!(reader.positions(), &[ChannelPosition::FrontLeft, ChannelPosition::FrontRight]);
+    assert_eq!(reader.read().unwrap(), Some(frame));
+    assert_eq!(reader.read().unwrap().map(|f| f.sequence), Some(8));
+    assert_eq!(reader.read().unwrap(), None);
+}
+
+#[test]
+fn test_recording_version() {
+    use std::io::Cursor;
+    let mut buffer = Vec::new();
+    FrameWriter::new(&mut buffer, 44100, &ChannelPosition::pulse_order(2)).unwrap();
+    buffer[7] = b'2';
+    let error = FrameReader::new(Cursor::new(buffer)).err().unwrap();
+    assert!(error.starts_with("Unsupported recording version 02"), "{}", error);
+    assert_eq!(FrameReader::new(Cursor::new(b"RIFF\0\0\0\0".to_vec())).err(),
+               Some("Not a frame recording".to_string()));
+}
@@ -1,7 +1,13 @@
 use libc::{c_char, c_void, c_int};
 use libpulse_sys::*;
 use std::ffi::{CStr, CString};
+use std::path::PathBuf;
 use std::ptr::{null, null_mut};
+use std::sync::mpsc::Sender;
+
+use super::ChannelPosition;
+use super::generator::GeneratorSpec;
+use super::pipe::PipeSpec;
 
 // returns (default source, all sources)
 pub fn get_sources() -> Result<(String, Vec<Option<PaSourceInfo>>), String> {
@@ -40,12 +46,68 @@ pub fn get_sources() -> Result<(String, Vec<Option<PaSourceInfo>>), String> {
     }
 }
 
+// changes to the pulseaudio sources after startup
+pub enum SourceEvent {
+    // pulseaudio index, info
+    Added(usize, PaSourceInfo),
+    // pulseaudio index
+    Removed(usize),
+    // name of the new default source (the default sink's monitor)
+    DefaultChanged(String),
+}
+
+// watches for sources being plugged in or removed and the default sink changing, sending events until the connection
+// drops or nothing listens anymore - blocks, so give it its own thread.
+// ready gets a message once the subscription is active, query the sources after that so none slip through
+pub fn watch_sources(sender: Sender<SourceEvent>, ready: Sender<()>) -> Result<(), String> {
+    unsafe {
+        let mainloop = pa_mainloop_new();
+        let api = pa_mainloop_get_api(mainloop);
+        let name = CString::new("Source Watcher").unwrap();
+        let ctxt = pa_context_new(api, name.as_ptr() as *const c_char);
+        let mut watch_data = WatchData {
+            sender: sender,
+            ready: Some(ready),
+            mainloop: mainloop,
+        };
+        let userdata = &mut watch_data as *mut WatchData as *mut c_void;
+        pa_context_set_state_callback(ctxt, Some(watch_state_cb), userdata);
+        pa_context_set_subscribe_callback(ctxt, Some(subscribe_cb), userdata);
+        pa_context_connect(ctxt, null(), PA_CONTEXT_NOFLAGS, null());
+        let mut ret: c_int = 0;
+        pa_mainloop_run(mainloop, &mut ret);
+        pa_context_disconnect(ctxt);
+        pa_context_unref(ctxt);
+        pa_mainloop_free(mainloop);
+        if ret != 0 {
+            Err("Lost connection while watching audio devices".to_string())
+        } else {
+            Ok(())
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct PaSourceInfo {
     pub name: String,
     pub description: String,
     pub rate: u32,
     pub channels: u8,
+    // where each channel goes, in the order the source delivers them
+    pub positions: Vec<ChannelPosition>,
+    pub kind: SourceKind,
+}
+
+// where the audio of a source actually comes from
+#[derive(Clone, Debug)]
+pub enum SourceKind {
+    Pulse,
+    File(PathBuf),
+    Pipe(PipeSpec),
+    // test signals, no hardware needed
+    Generator(GeneratorSpec),
+    // analysis frames recorded earlier, played back instead of analysing anything
+    Replay(PathBuf),
 }
 
 // we can ignore the sample format and spec - only need rate for pulse-simple api
@@ -65,6 +127,23 @@ impl Into<PaSourceInfo> for RawSourceInfo {
             description: self.description,
             rate: self.rate,
             channels: self.channels,
+            positions: ChannelPosition::pulse_order(self.channels as usize),
+            kind: SourceKind::Pulse,
+        }
+    }
+}
+
+impl RawSourceInfo {
+    unsafe fn from_pa(source_info: &pa_source_info) -> Self {
+        RawSourceInfo {
+            index: source_info.index as usize,
+            name: CStr::from_ptr(source_info.name).to_str().unwrap().to_string(),
+            description: CStr::from_ptr(source_info.description)
+                .to_str()
+                .unwrap()
+                .to_string(),
+            rate: source_info.sample_spec.rate,
+            channels: source_info.sample_spec.channels,
         }
     }
 }
@@ -82,18 +161,7 @@ unsafe extern "C" fn get_sources_cb(_: *mut pa_context,
     let ref mut cb_data = *(userdata as *mut CbData);
     if eol == 0 {
         // still more entries
-        let source_info = *i;
-        let rs_source_info = RawSourceInfo {
-            index: source_info.index as usize,
-            name: CStr::from_ptr(source_info.name).to_str().unwrap().to_string(),
-            description: CStr::from_ptr(source_info.description)
-                .to_str()
-                .unwrap()
-                .to_string(),
-            rate: source_info.sample_spec.rate,
-            channels: source_info.sample_spec.channels,
-        };
-        cb_data.items.push(rs_source_info);
+        cb_data.items.push(RawSourceInfo::from_pa(&*i));
     } else if eol > 0 {
         // no more entries
         pa_mainloop_quit(cb_data.mainloop, 0);
@@ -125,3 +193,87 @@ unsafe extern "C" fn state_cb(ctxt: *mut pa_context, userdata: *mut c_void) {
         pa_mainloop_free(cb_data.mainloop);
     }
 }
+
+struct WatchData {
+    sender: Sender<SourceEvent>,
+    ready: Option<Sender<()>>,
+    mainloop: *mut pa_mainloop,
+}
+
+unsafe extern "C" fn watch_state_cb(ctxt: *mut pa_context, userdata: *mut c_void) {
+    let state = pa_context_get_state(ctxt);
+    if state == PA_CONTEXT_READY {
+        pa_operation_unref(pa_context_subscribe(ctxt,
+                                                PA_SUBSCRIPTION_MASK_SOURCE |
+                                                PA_SUBSCRIPTION_MASK_SERVER,
+                                                Some(subscribed_cb),
+                                                userdata));
+    } else if state == PA_CONTEXT_FAILED || state == PA_CONTEXT_TERMINATED {
+        let ref mut watch_data = *(userdata as *mut WatchData);
+        pa_mainloop_quit(watch_data.mainloop, 1);
+    }
+}
+
+unsafe extern "C" fn subscribed_cb(_: *mut pa_context, _: c_int, userdata: *mut c_void) {
+    let ref mut watch_data = *(userdata as *mut WatchData);
+    // whoever waits can go on even if subscribing failed, the connection dropping ends the watch
+    if let Some(ready) = watch_data.ready.take() {
+        let _ = ready.send(());
+    }
+}
+
+unsafe extern "C" fn subscribe_cb(ctxt: *mut pa_context,
+                                  event: pa_subscription_event_type_t,
+                                  idx: u32,
+                                  userdata: *mut c_void) {
+    let ref mut watch_data = *(userdata as *mut WatchData);
+    let facility = event & PA_SUBSCRIPTION_EVENT_FACILITY_MASK;
+    let event_type = event & PA_SUBSCRIPTION_EVENT_TYPE_MASK;
+    if facility == PA_SUBSCRIPTION_EVENT_SERVER {
+        // the default sink is part of the server info
+        pa_operation_unref(pa_context_get_server_info(ctxt, Some(server_info_cb), userdata));
+        return;
+    }
+    if facility != PA_SUBSCRIPTION_EVENT_SOURCE {
+        return;
+    }
+    if event_type == PA_SUBSCRIPTION_EVENT_NEW {
+        // need the full info before anyone can use it
+        pa_operation_unref(pa_context_get_source_info_by_index(ctxt,
+                                                               idx,
+                                                               Some(new_source_cb),
+                                                               userdata));
+    } else if event_type == PA_SUBSCRIPTION_EVENT_REMOVE {
+        if watch_data.sender.send(SourceEvent::Removed(idx as usize)).is_err() {
+            pa_mainloop_quit(watch_data.mainloop, 0);
+        }
+    }
+}
+
+unsafe extern "C" fn new_source_cb(_: *mut pa_context,
+                                   i: *const pa_source_info,
+                                   eol: c_int,
+                                   userdata: *mut c_void) {
+    let ref mut watch_data = *(userdata as *mut WatchData);
+    // the source can already be gone again, then there is no info and eol is set
+    if eol == 0 && i != null() {
+        let raw = RawSourceInfo::from_pa(&*i);
+        if watch_data.sender.send(SourceEvent::Added(raw.index, raw.into())).is_err() {
+            pa_mainloop_quit(watch_data.mainloop, 0);
+        }
+    }
+}
+
+unsafe extern "C" fn server_info_cb(_: *mut pa_context,
+                                    i: *const pa_server_info,
+                                    userdata: *mut c_void) {
+    let ref mut watch_data = *(userdata as *mut WatchData);
+    if i == null() || (*i).default_sink_name == null() {
+        return;
+    }
+    let name = format!("{}.monitor",
+                       CStr::from_ptr((*i).default_sink_name).to_string_lossy());
+    if watch_data.sender.send(SourceEvent::DefaultChanged(name)).is_err() {
+        pa_mainloop_quit(watch_data.mainloop, 0);
+    }
+}
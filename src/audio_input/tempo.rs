@@ -0,0 +1,141 @@
+use std::collections::VecDeque;
+
+use super::AudioFrame;
+
+// range of tempos that get considered
+const MIN_BPM: f64 = 60.;
+const MAX_BPM: f64 = 200.;
+// seconds of onset history the tempo is estimated from
+const HISTORY_SECS: f64 = 8.;
+// seconds between estimates, the autocorrelation is too expensive for every frame
+const ESTIMATE_SECS: f64 = 0.5;
+// how strong the periodicity has to be compared to the signal's own energy
+const MIN_CONFIDENCE: f64 = 0.1;
+
+// Estimates tempo by autocorrelating the onset strength of the last few seconds, then finds
+// where the beats fall by lining a comb with that period up against the onsets.
+pub struct TempoTracker {
+    // seconds between two frames
+    frame_secs: f64,
+    history: VecDeque<f64>,
+    history_frames: usize,
+    estimate_frames: usize,
+    since_estimate: usize,
+    // beat period in frames, if there is a tempo
+    period: Option<f64>,
+    // 0 on a beat, rising to 1 right before the next one
+    phase: f64,
+}
+
+impl TempoTracker {
+    // frame_ns: time between two frames
+    pub fn new(frame_ns: u64) -> Self {
+        let frame_secs = frame_ns.max(1) as f64 / 1e9;
+        let history_frames = (HISTORY_SECS / frame_secs) as usize;
+        TempoTracker {
+            frame_secs: frame_secs,
+            history: VecDeque::with_capacity(history_frames),
+            history_frames: history_frames,
+            estimate_frames: ((ESTIMATE_SECS / frame_secs) as usize).max(1),
+            since_estimate: 0,
+            period: None,
+            phase: 0.,
+        }
+    }
+
+    // fills in the tempo fields of a frame whose onset strength is already there
+    pub fn process(&mut self, frame: &mut AudioFrame) {
+        if self.history.len() == self.history_frames {
+            self.history.pop_front();
+        }
+        self.history.push_back(frame.onset_strength);
+        if let Some(period) = self.period {
+            self.phase = (self.phase + 1. / period) % 1.;
+        }
+        self.since_estimate += 1;
+        // wait until there is enough history for the slowest tempo to show up twice
+        let min_frames = (2. * 60. / MIN_BPM / self.frame_secs) as usize;
+        if self.since_estimate >= self.estimate_frames && self.history.len() >= min_frames {
+            self.since_estimate = 0;
+            self.estimate();
+        }
+        frame.bpm = self.period.map(|period| 60. / (period * self.frame_secs));
+        frame.beat_phase = self.phase;
+    }
+
+    fn estimate(&mut self) {
+        let onsets: Vec<f64> = self.history.iter().cloned().collect();
+        let n = onsets.len();
+        let mean = onsets.iter().sum::<f64>() / n as f64;
+        let centered: Vec<f64> = onsets.iter().map(|o| o - mean).collect();
+        // not normalized by the overlap, so multiples of the period score a bit lower than it
+        let autocorrelation = |lag: usize| -> f64 {
+            centered[lag..].iter().zip(centered.iter()).map(|(a, b)| a * b).sum::<f64>() / n as f64
+        };
+        let energy = autocorrelation(0);
+        let min_lag = ((60. / MAX_BPM / self.frame_secs).floor() as usize).max(1);
+        let max_lag = ((60. / MIN_BPM / self.frame_secs).ceil() as usize).min(n - 1);
+        if energy <= 0. || min_lag + 2 > max_lag {
+            self.period = None;
+            return;
+        }
+        let scores: Vec<f64> = (min_lag - 1..max_lag + 2).map(|lag| autocorrelation(lag.min(n - 1))).collect();
+        let (best, best_score) = (1..scores.len() - 1)
+            .map(|i| (i, scores[i]))
+            .fold((1, ::std::f64::MIN), |best, s| if s.1 > best.1 { s } else { best });
+        if best_score / energy < MIN_CONFIDENCE {
+            self.period = None;
+            return;
+        }
+        // parabolic interpolation between the neighbouring lags for a sub frame period
+        let (left, right) = (scores[best - 1], scores[best + 1]);
+        let curvature = left - 2. * best_score + right;
+        let offset = if curvature < 0. {
+            0.5 * (left - right) / curvature
+        } else {
+            0.
+        };
+        let period = (min_lag - 1 + best) as f64 + offset;
+
+        // how many frames ago the last beat was: the offset where a comb of the period
+        // catches the most onset strength
+        let (beat_offset, _) = (0..period.ceil() as usize)
+            .map(|start| {
+                let mut score = 0.;
+                let mut pos = start as f64;
+                while (pos as usize) < n {
+                    score += onsets[n - 1 - pos as usize];
+                    pos += period;
+                }
+                (start, score)
+            })
+            .fold((0, ::std::f64::MIN), |best, s| if s.1 > best.1 { s } else { best });
+        self.period = Some(period);
+        self.phase = (beat_offset as f64 / period) % 1.;
+    }
+}
+
+#[test]
+fn test_tempo_steady_pulse() {
+    // 10 ms frames with an onset every 50 frames, 120 bpm
+    let mut tracker = TempoTracker::new(10_000_000);
+    let mut frame = AudioFrame::default();
+    for n in 0..1000 {
+        frame.onset_strength = if n % 50 == 0 { 1. } else { 0. };
+        tracker.process(&mut frame);
+    }
+    let bpm = frame.bpm.unwrap();
+    assert!((bpm - 120.).abs() < 1., "bpm {}", bpm);
+    // the last onset was 49 frames ago, the next one is due
+    assert!((frame.beat_phase - 49. / 50.).abs() < 0.05, "phase {}", frame.beat_phase);
+}
+
+#[test]
+fn test_tempo_silence() {
+    let mut tracker = TempoTracker::new(10_000_000);
+    let mut frame = AudioFrame::default();
+    for _ in 0..1000 {
+        tracker.process(&mut frame);
+    }
+    assert_eq!(frame.bpm, None);
+}
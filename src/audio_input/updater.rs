@@ -1,43 +1,84 @@
 use std::collections::HashMap;
-use std::sync::mpsc::{channel, Receiver, TryRecvError};
+use std::path::Path;
 use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::time::Duration;
 
 use gtk::prelude::*;
 
-use super::{AudioFrame, FRAMES};
-use super::{get_sources, PaSourceInfo};
-use super::processor::AudioProcessor;
+use super::{AudioFrame, FrameContent};
+use super::{get_sources, PaSourceInfo, SourceEvent, SourceKind};
+use super::processor::ProcessorSettingsTable;
+use super::recording::{FrameWriter, SharedRecorder};
+use super::worker::CaptureWorker;
 use message::UpdateMessage;
-use shared_data::{SharedData, ContinueState};
+use shared_data::{frame_exchange, ContinueState, FrameReceiver};
+
+// a source that instances are drawing from
+pub struct SourceMapping {
+    // stops capturing once the mapping is dropped
+    worker: CaptureWorker,
+    ids: Vec<usize>,
+}
 
 pub struct AudioUpdater {
     default_source_name: String,
     pub sources: Vec<Option<PaSourceInfo>>,
+    processor_settings: ProcessorSettingsTable,
     // multiple renderers per audio processor - have list of processors and map them to avoid
     // overuse of audio resources and conflicts
     // array w/ size of max index + 1
-    // vec<usize> are the ids
-    pub audio_processor_mappings: Vec<Option<(AudioProcessor, Vec<usize>)>>,
+    pub audio_processor_mappings: Vec<Option<SourceMapping>>,
     // receiver for deletion messages
     msg_receiver: Receiver<UpdateMessage>,
-    pub current_data: Vec<SharedData>,
+    // hot plug events from the source watcher
+    source_events: Receiver<SourceEvent>,
+    // tells the app about sources coming and going
+    app_sender: Sender<UpdateMessage>,
+    // pulseaudio index -> index in sources, sources plugged in after startup go at the end
+    pulse_indices: HashMap<usize, usize>,
+    // ids of instances that move along whenever the default source changes
+    followers: Vec<usize>,
+    // ids of instances whose source got unplugged, by source name, they go back on it once it returns
+    orphans: HashMap<String, Vec<usize>>,
+    // what every instance needs in its frames, missing ids only want the spectrum
+    contents: HashMap<usize, FrameContent>,
+    // recordings opened so far, by source name
+    recorders: HashMap<String, SharedRecorder>,
     program_continue: ContinueState,
 }
 
 impl AudioUpdater {
     pub fn new(default_source_name: &str,
                sources: Vec<Option<PaSourceInfo>>,
-               audio_processor_mappings: Vec<Option<(AudioProcessor, Vec<usize>)>>,
+               processor_settings: ProcessorSettingsTable,
+               audio_processor_mappings: Vec<Option<SourceMapping>>,
                msg_receiver: Receiver<UpdateMessage>,
-               data: Vec<SharedData>,
+               source_events: Receiver<SourceEvent>,
+               app_sender: Sender<UpdateMessage>,
                program_continue: ContinueState)
                -> Self {
+        // at startup the pulseaudio sources sit at their own index
+        let pulse_indices = sources.iter()
+            .enumerate()
+            .filter_map(|(i, s)| match *s {
+                Some(PaSourceInfo { kind: SourceKind::Pulse, .. }) => Some((i, i)),
+                _ => None,
+            })
+            .collect();
         AudioUpdater {
             default_source_name: default_source_name.to_string(),
             sources: sources,
+            processor_settings: processor_settings,
             audio_processor_mappings: audio_processor_mappings,
             msg_receiver: msg_receiver,
-            current_data: data,
+            source_events: source_events,
+            app_sender: app_sender,
+            pulse_indices: pulse_indices,
+            followers: Vec::new(),
+            orphans: HashMap::new(),
+            contents: HashMap::new(),
+            recorders: HashMap::new(),
             program_continue: program_continue,
         }
     }
@@ -53,9 +94,61 @@ impl AudioUpdater {
         None
     }
 
+    // where an instance currently gets its data from
+    fn index_of_id(&self, id: usize) -> Option<usize> {
+        self.audio_processor_mappings.iter().position(|m| match *m {
+            Some(ref mapping) => mapping.ids.contains(&id),
+            None => false,
+        })
+    }
+
+    // move every following instance over to the default source
+    fn retarget_followers(&mut self) -> Result<(), String> {
+        let new_index = match self.default_source_index() {
+            Some(index) => index,
+            // the default sink's monitor isn't known yet, wait for it to be added
+            None => return Ok(()),
+        };
+        for id in self.followers.clone() {
+            match self.index_of_id(id) {
+                Some(old_index) if old_index == new_index => {}
+                Some(old_index) => {
+                    try!(self.assign_id_to_index(id, new_index));
+                    self.remove_id_from_index(id, old_index);
+                }
+                // its source got unplugged
+                None => try!(self.assign_id_to_index(id, new_index)),
+            }
+        }
+        self.app_sender
+            .send(UpdateMessage::DefaultChanged(new_index))
+            .map_err(|e| format!("{}", e))
+    }
+
+    fn forget_orphan(&mut self, id: usize) {
+        for ids in self.orphans.values_mut() {
+            ids.retain(|&o| o != id);
+        }
+        self.orphans.retain(|_, ids| !ids.is_empty());
+    }
+
+    // workers only produce the extras while an instance on them draws them
+    fn update_worker_contents(&self) {
+        for mapping in self.audio_processor_mappings.iter() {
+            if let Some(ref mapping) = *mapping {
+                let content = mapping.ids
+                    .iter()
+                    .filter_map(|id| self.contents.get(id))
+                    .fold(FrameContent::spectrum(), |acc, &content| acc.union(content));
+                mapping.worker.set_content(content);
+            }
+        }
+    }
+
     pub fn remove_id_from_index(&mut self, id: usize, index: usize) {
         let mut rm_audio_processor = false;
-        if let Some((_, ref mut ids)) = self.audio_processor_mappings[index] {
+        // the source might have been unplugged already
+        if let Some(&mut Some(SourceMapping { ref mut ids, .. })) = self.audio_processor_mappings.get_mut(index) {
             for i in (0..ids.len()).rev() {
                 if ids[i] == id {
                     ids.swap_remove(i);
@@ -65,71 +158,179 @@ impl AudioUpdater {
                 rm_audio_processor = true;
             }
         }
-        // remove audio processor if no more instances are using it
+        // remove audio processor if no more instances are using it, whatever still reads
+        // the source sees no signal instead of its last frame
         if rm_audio_processor {
             self.audio_processor_mappings[index] = None;
-            *self.current_data[index].lock().unwrap() = None;
+            let _ = self.app_sender.send(UpdateMessage::SourceData(index, FrameReceiver::empty()));
         }
     }
 
     pub fn assign_id_to_index(&mut self, id: usize, index: usize) -> Result<(), String> {
-        if let Some((_, ref mut ids)) = self.audio_processor_mappings[index] {
-            ids.push(id);
+        if let Some(&mut Some(ref mut mapping)) = self.audio_processor_mappings.get_mut(index) {
+            mapping.ids.push(id);
             return Ok(());
         }
-        // if the processor doesn't exist, create it
-        match AudioProcessor::new(self.sources.as_slice(), index) {
-            Some(processor) => {
-                *self.current_data[index].lock().unwrap() =
-                    Some(vec![vec![0f64; FRAMES]; processor.channels()]);
-                self.audio_processor_mappings[index] = Some((processor, vec![id]));
-                Ok(())
-            }
-            None => Err(format!("Could not set id {} to index {}", id, index)),
+        // if the worker doesn't exist, start it on an exchange of its own
+        let (publisher, receiver) = frame_exchange();
+        let source = match self.sources.get(index) {
+            Some(&Some(ref source)) => source.clone(),
+            _ => return Err(format!("Could not set id {} to index {}", id, index)),
+        };
+        let settings = self.processor_settings.for_source(&source.name).clone();
+        let recorder = match settings.record {
+            Some(ref path) => self.recorder(&source, path),
+            None => None,
+        };
+        let worker = CaptureWorker::spawn(index, source, settings, recorder, publisher);
+        try!(self.app_sender
+            .send(UpdateMessage::SourceData(index, receiver))
+            .map_err(|e| format!("{}", e)));
+        self.audio_processor_mappings[index] = Some(SourceMapping {
+            worker: worker,
+            ids: vec![id],
+        });
+        Ok(())
+    }
+
+    // the source's recording, opened the first time it gets captured
+    fn recorder(&mut self, source: &PaSourceInfo, path: &Path) -> Option<SharedRecorder> {
+        if let Some(recorder) = self.recorders.get(&source.name) {
+            return Some(recorder.clone());
+        }
+        match FrameWriter::create(path, source.rate, &source.positions) {
+            Ok(writer) => {
+                let recorder = Arc::new(Mutex::new(writer));
+                self.recorders.insert(source.name.clone(), recorder.clone());
+                Some(recorder)
+            }
+            // tried again the next time the source gets captured
+            Err(e) => {
+                println!("Not recording audio source {}: {}", source.name, e);
+                None
+            }
         }
     }
 
     fn handle_message(&mut self, message: UpdateMessage) -> Result<(), String> {
         match message {
-            UpdateMessage::Destroy(id, index) => {
-                self.remove_id_from_index(id, index);
+            // the index the instance sends can be behind, followers get moved over in here
+            UpdateMessage::Destroy(id, _) => {
+                self.followers.retain(|&f| f != id);
+                self.forget_orphan(id);
+                self.contents.remove(&id);
+                if let Some(index) = self.index_of_id(id) {
+                    self.remove_id_from_index(id, index);
+                }
             }
-            UpdateMessage::ChangeMapping(id, old_idx, new_idx) => {
-                try!(self.assign_id_to_index(id, new_idx));
-                self.remove_id_from_index(id, old_idx);
+            UpdateMessage::ChangeMapping(id, _, new_idx) => {
+                self.forget_orphan(id);
+                match self.index_of_id(id) {
+                    Some(old_idx) if old_idx == new_idx => {}
+                    Some(old_idx) => {
+                        try!(self.assign_id_to_index(id, new_idx));
+                        self.remove_id_from_index(id, old_idx);
+                    }
+                    // reattaching to a source that was missing
+                    None => try!(self.assign_id_to_index(id, new_idx)),
+                }
             }
             UpdateMessage::Add(id, index) => {
                 try!(self.assign_id_to_index(id, index));
             }
+            UpdateMessage::Content(id, content) => {
+                self.contents.insert(id, content);
+            }
+            UpdateMessage::FollowDefault(id, follow) => {
+                self.followers.retain(|&f| f != id);
+                if follow {
+                    self.forget_orphan(id);
+                    self.followers.push(id);
+                    try!(self.retarget_followers());
+                }
+            }
+            // only ever sent to the app
+            UpdateMessage::SourceAdded(..) |
+            UpdateMessage::SourceData(..) |
+            UpdateMessage::SourceRemoved(_) |
+            UpdateMessage::DefaultChanged(_) => {}
         }
         Ok(())
     }
 
-    pub fn iterate(&mut self) -> Result<(), String> {
-        // check all messages
-        match self.msg_receiver.try_recv() {
-            Ok(m) => try!(self.handle_message(m)),
-            Err(e) => match e {
-                TryRecvError::Empty => {},
-                TryRecvError::Disconnected => {
-                    return Err(format!("{}", e));
+    fn handle_source_event(&mut self, event: SourceEvent) -> Result<(), String> {
+        match event {
+            SourceEvent::Added(pa_index, info) => {
+                // the startup query can overlap with the watcher
+                if self.pulse_indices.contains_key(&pa_index) {
+                    return Ok(());
+                }
+                // indices are never reused, an instance can't end up on some other device
+                let index = self.sources.len();
+                let name = info.name.clone();
+                self.pulse_indices.insert(pa_index, index);
+                self.sources.push(Some(info.clone()));
+                self.audio_processor_mappings.push(None);
+                try!(self.app_sender
+                    .send(UpdateMessage::SourceAdded(index, info))
+                    .map_err(|e| format!("{}", e)));
+                for id in self.orphans.remove(&name).unwrap_or_else(Vec::new) {
+                    if self.index_of_id(id).is_none() {
+                        try!(self.assign_id_to_index(id, index));
+                    }
+                }
+                if name == self.default_source_name {
+                    try!(self.retarget_followers());
+                }
+            }
+            SourceEvent::Removed(pa_index) => {
+                if let Some(index) = self.pulse_indices.remove(&pa_index) {
+                    // instances on it stop drawing, the processor can't read anymore anyway
+                    let name = self.sources[index].take().map(|info| info.name);
+                    let mapping = self.audio_processor_mappings[index].take();
+                    if let (Some(mapping), Some(name)) = (mapping, name) {
+                        // followers go wherever the default ends up instead
+                        let followers = &self.followers;
+                        self.orphans
+                            .entry(name)
+                            .or_insert_with(Vec::new)
+                            .extend(mapping.ids.into_iter().filter(|id| !followers.contains(id)));
+                    }
+                    try!(self.app_sender
+                        .send(UpdateMessage::SourceRemoved(index))
+                        .map_err(|e| format!("{}", e)));
                 }
-            },
+            }
+            SourceEvent::DefaultChanged(name) => {
+                if name != self.default_source_name {
+                    self.default_source_name = name;
+                    try!(self.retarget_followers());
+                }
+            }
         }
+        Ok(())
+    }
 
-        // set data from audio processors
-        if self.audio_processor_mappings.iter().all(|m| m.is_none()) {
-            ::std::thread::sleep(::std::time::Duration::from_millis(50));
+    // the workers do the capturing, this only keeps the mappings up to date
+    pub fn iterate(&mut self) -> Result<(), String> {
+        // wait for messages, a bad message only affects the instance that sent it
+        match self.msg_receiver.recv_timeout(Duration::from_millis(50)) {
+            Ok(m) => {
+                if let Err(e) = self.handle_message(m) {
+                    println!("{}", e);
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(e @ RecvTimeoutError::Disconnected) => {
+                return Err(format!("{}", e));
+            }
         }
 
-        for mapping in self.audio_processor_mappings.iter_mut() {
-            if let Some((ref mut processor, _)) = *mapping {
-                let data = processor.get_data_frame();
-                *self.current_data[processor.source_index()]
-                    .lock()
-                    .unwrap() = Some(data);
-            }
+        // the watcher going away only means no more hot plugging
+        while let Ok(event) = self.source_events.try_recv() {
+            try!(self.handle_source_event(event));
         }
+        self.update_worker_contents();
         Ok(())
     }
 }
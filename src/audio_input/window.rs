@@ -0,0 +1,81 @@
+use std::f64::consts::PI;
+
+// Applied to each block of samples before the fourier transform to cut down on spectral
+// leakage. Rectangular leaves the block untouched.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum WindowFunction {
+    Rectangular,
+    Hann,
+    Hamming,
+    BlackmanHarris,
+    FlatTop,
+}
+
+impl Default for WindowFunction {
+    fn default() -> Self {
+        WindowFunction::Hann
+    }
+}
+
+impl WindowFunction {
+    // Periodic window of length n, scaled so that its coefficients sum up to n.
+    // That way a pure tone keeps the same peak magnitude whichever window is picked.
+    pub fn coefficients(&self, n: usize) -> Vec<f64> {
+        let cosine_terms: &[f64] = match *self {
+            WindowFunction::Rectangular => &[1.],
+            WindowFunction::Hann => &[0.5, 0.5],
+            WindowFunction::Hamming => &[0.54, 0.46],
+            WindowFunction::BlackmanHarris => &[0.35875, 0.48829, 0.14128, 0.01168],
+            WindowFunction::FlatTop => {
+                &[0.21557895, 0.41663158, 0.277263158, 0.083578947, 0.006947368]
+            }
+        };
+        let mut coefficients = (0..n)
+            .map(|i| {
+                let x = 2. * PI * i as f64 / n as f64;
+                // a0 - a1 cos(x) + a2 cos(2x) - a3 cos(3x) ...
+                cosine_terms.iter()
+                    .enumerate()
+                    .fold(0., |acc, (k, a)| {
+                        let term = a * (k as f64 * x).cos();
+                        if k % 2 == 0 { acc + term } else { acc - term }
+                    })
+            })
+            .collect::<Vec<f64>>();
+        let sum = coefficients.iter().fold(0., |acc, &c| acc + c);
+        if sum != 0. {
+            for c in coefficients.iter_mut() {
+                *c *= n as f64 / sum;
+            }
+        }
+        coefficients
+    }
+}
+
+#[test]
+fn test_window_rectangular() {
+    let window = WindowFunction::Rectangular.coefficients(8);
+    assert_eq!(window.as_slice(), &[1.; 8]);
+}
+
+#[test]
+fn test_window_hann() {
+    let window = WindowFunction::Hann.coefficients(4);
+    // 0, 0.5, 1, 0.5 before scaling up to sum to 4
+    for (c, expected) in window.iter().zip([0., 1., 2., 1.].iter()) {
+        assert!((c - expected).abs() < 1e-9);
+    }
+}
+
+#[test]
+fn test_window_normalized() {
+    for window in [WindowFunction::Hamming, WindowFunction::BlackmanHarris, WindowFunction::FlatTop].iter() {
+        let coefficients = window.coefficients(256);
+        let sum = coefficients.iter().fold(0., |acc, &c| acc + c);
+        assert!((sum - 256.).abs() < 1e-9);
+        // periodic windows are symmetric around the middle sample
+        for i in 1..128 {
+            assert!((coefficients[i] - coefficients[256 - i]).abs() < 1e-9);
+        }
+    }
+}
@@ -0,0 +1,200 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::{find_positions, ChannelPosition, FrameContent, PaSourceInfo, ProcessorSettings, SourceKind};
+use super::backend::open_backend;
+use super::processor::AudioProcessor;
+use super::recording::{FrameReader, SharedRecorder};
+use shared_data::FramePublisher;
+
+// first retry after a source fails, doubled after every failure in a row
+const RETRY_DELAY_MS: u64 = 500;
+const MAX_RETRY_DELAY_MS: u64 = 30_000;
+// how often a worker waiting for a retry checks whether it should stop
+const STOP_CHECK_MS: u64 = 50;
+// longer than this between two recorded frames, the source wasn't captured in between
+const MAX_REPLAY_GAP_MS: u64 = 1000;
+
+// Captures and analyzes one source on its own thread, so a blocking or stalled device
+// only holds up the instances drawing from it.
+// The backend gets opened inside the thread, nothing pulseaudio related crosses threads.
+pub struct CaptureWorker {
+    stop: Arc<AtomicBool>,
+    // what the frames should carry besides the spectrum, picked up before the next frame
+    waveform: Arc<AtomicBool>,
+    mid_side: Arc<AtomicBool>,
+}
+
+impl CaptureWorker {
+    pub fn spawn(index: usize,
+                 source: PaSourceInfo,
+                 settings: ProcessorSettings,
+                 recorder: Option<SharedRecorder>,
+                 mut data: FramePublisher)
+                 -> CaptureWorker {
+        let stop = Arc::new(AtomicBool::new(false));
+        let waveform = Arc::new(AtomicBool::new(false));
+        let mid_side = Arc::new(AtomicBool::new(false));
+        {
+            let stop = stop.clone();
+            let waveform = waveform.clone();
+            let mid_side = mid_side.clone();
+            thread::spawn(move || match source.kind {
+                SourceKind::Replay(ref path) => replay(index, path, &mut data, &stop),
+                _ => run(index, &source, settings, recorder, &mut data, &stop, waveform, mid_side),
+            });
+        }
+        CaptureWorker {
+            stop: stop,
+            waveform: waveform,
+            mid_side: mid_side,
+        }
+    }
+
+    pub fn set_content(&self, content: FrameContent) {
+        self.waveform.store(content.waveform, Ordering::SeqCst);
+        self.mid_side.store(content.mid_side, Ordering::SeqCst);
+    }
+}
+
+// the thread can be stuck in a read, so it isn't joined - it exits after the read returns
+impl Drop for CaptureWorker {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+    }
+}
+
+fn run(index: usize,
+       source: &PaSourceInfo,
+       settings: ProcessorSettings,
+       mut recorder: Option<SharedRecorder>,
+       data: &mut FramePublisher,
+       stop: &AtomicBool,
+       waveform: Arc<AtomicBool>,
+       mid_side: Arc<AtomicBool>) {
+    // failures in a row, reset once a frame gets through
+    let mut failures = 0;
+    let left_right = find_positions(&source.positions, &[ChannelPosition::FrontLeft, ChannelPosition::FrontRight])
+        .map(|found| (found[0], found[1]));
+    while !stop.load(Ordering::SeqCst) {
+        let mut processor = match open_backend(source) {
+            Ok(backend) => AudioProcessor::new(index, backend, &source.positions, &settings),
+            Err(e) => {
+                println!("Could not open audio source {}: {}", index, e);
+                wait_for_retry(&mut failures, stop);
+                continue;
+            }
+        };
+        // reused for every frame
+        let mut frame = processor.empty_frame();
+        // stopped while opening, the instances have moved on to another worker
+        if stop.load(Ordering::SeqCst) {
+            return;
+        }
+        data.write(&frame);
+        while !stop.load(Ordering::SeqCst) {
+            processor.set_waveform(waveform.load(Ordering::SeqCst));
+            processor.set_mid_side(if mid_side.load(Ordering::SeqCst) {
+                left_right
+            } else {
+                None
+            });
+            match processor.get_data_frame_into(&mut frame) {
+                Ok(()) => {
+                    failures = 0;
+                    // stopped while reading, the instances have moved on to another worker
+                    if stop.load(Ordering::SeqCst) {
+                        return;
+                    }
+                    data.write(&frame);
+                    // a recording that can't be written doesn't stop the capture
+                    if let Some(Err(e)) = recorder.as_ref().map(|r| r.lock().unwrap().write(&frame)) {
+                        println!("Stopped recording audio source {}: {}", index, e);
+                        recorder = None;
+                    }
+                }
+                // only the instances on this source lose their signal
+                Err(e) => {
+                    println!("Audio source {} failed: {}", index, e);
+                    if stop.load(Ordering::SeqCst) {
+                        return;
+                    }
+                    data.clear();
+                    wait_for_retry(&mut failures, stop);
+                    break;
+                }
+            }
+        }
+    }
+}
+
+// play a recording back at the pace it was made at, starting over once it ends
+fn replay(index: usize, path: &Path, data: &mut FramePublisher, stop: &AtomicBool) {
+    let mut failures = 0;
+    while !stop.load(Ordering::SeqCst) {
+        let mut reader = match FrameReader::open(path) {
+            Ok(reader) => reader,
+            Err(e) => {
+                println!("Could not open audio source {}: {}", index, e);
+                wait_for_retry(&mut failures, stop);
+                continue;
+            }
+        };
+        // when the first frame of the recording is shown
+        let mut start: Option<(u64, Instant)> = None;
+        let mut previous = None;
+        loop {
+            let frame = match reader.read() {
+                Ok(Some(frame)) => frame,
+                Ok(None) => break,
+                Err(e) => {
+                    println!("Audio source {} failed: {}", index, e);
+                    if stop.load(Ordering::SeqCst) {
+                        return;
+                    }
+                    data.clear();
+                    wait_for_retry(&mut failures, stop);
+                    break;
+                }
+            };
+            // pauses in capturing aren't waited out again, the frames after them come right away
+            if let Some(previous) = previous {
+                if frame.timestamp.saturating_sub(previous) > MAX_REPLAY_GAP_MS * 1_000_000 {
+                    start = None;
+                }
+            }
+            previous = Some(frame.timestamp);
+            let (first, started) = *start.get_or_insert((frame.timestamp, Instant::now()));
+            let offset = frame.timestamp.saturating_sub(first);
+            let due = started + Duration::new(offset / 1_000_000_000, (offset % 1_000_000_000) as u32);
+            loop {
+                let now = Instant::now();
+                if now >= due || stop.load(Ordering::SeqCst) {
+                    break;
+                }
+                thread::sleep(::std::cmp::min(due - now, Duration::from_millis(STOP_CHECK_MS)));
+            }
+            failures = 0;
+            if stop.load(Ordering::SeqCst) {
+                return;
+            }
+            data.write(&frame);
+        }
+        // an empty recording would otherwise spin
+        if start.is_none() {
+            wait_for_retry(&mut failures, stop);
+        }
+    }
+}
+
+fn wait_for_retry(failures: &mut u32, stop: &AtomicBool) {
+    let delay = RETRY_DELAY_MS.saturating_mul(1 << (*failures).min(16)).min(MAX_RETRY_DELAY_MS);
+    *failures += 1;
+    let retry_at = Instant::now() + Duration::from_millis(delay);
+    while Instant::now() < retry_at && !stop.load(Ordering::SeqCst) {
+        thread::sleep(Duration::from_millis(STOP_CHECK_MS));
+    }
+}
@@ -0,0 +1,108 @@
+use std::path::PathBuf;
+
+use audio_input::{probe_file, probe_recording, generator_source_info, pipe_source_info, GeneratorSpec,
+                  PaSourceInfo, PcmFormat, PipeSpec, ProcessorSettingsTable, Signal};
+
+// settings for the audio side of the program, kept apart from the instance list
+#[derive(Serialize, Deserialize)]
+pub struct AudioConfig {
+    // listed after the pulseaudio sources, in this order
+    #[serde(default)]
+    pub sources: Vec<SourceConfig>,
+    // whether a few test signals get listed after those without being configured
+    #[serde(default = "default_test_signals")]
+    pub test_signals: bool,
+    // analysis settings, with overrides for individual sources by name
+    #[serde(default)]
+    pub processors: ProcessorSettingsTable,
+}
+
+impl Default for AudioConfig {
+    fn default() -> Self {
+        AudioConfig {
+            sources: Vec::new(),
+            test_signals: true,
+            processors: ProcessorSettingsTable::default(),
+        }
+    }
+}
+
+fn default_test_signals() -> bool {
+    true
+}
+
+impl AudioConfig {
+    // the configured sources, then the built in test signals nobody configured already
+    pub fn source_configs(&self) -> Vec<SourceConfig> {
+        let mut configs = self.sources.clone();
+        if self.test_signals {
+            let signals = [Signal::Sine { frequency: 1000. }, Signal::PinkNoise, Signal::Metronome { bpm: 120. }];
+            for signal in signals.iter() {
+                // the source name comes from the signal, so the same signal twice would clash
+                let configured = self.sources.iter().any(|c| match *c {
+                    SourceConfig::Generator { signal: ref s, .. } => s == signal,
+                    _ => false,
+                });
+                if !configured {
+                    configs.push(SourceConfig::Generator {
+                        signal: signal.clone(),
+                        amplitude: 0.5,
+                        rate: 48000,
+                        channels: 2,
+                    });
+                }
+            }
+        }
+        configs
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub enum SourceConfig {
+    File { path: PathBuf },
+    // raw pcm from a fifo, or from standard input when the path is "-"
+    Pipe {
+        path: PathBuf,
+        format: PcmFormat,
+        rate: u32,
+        channels: u8,
+    },
+    // a synthetic test signal, amplitude is the peak as a fraction of full scale
+    Generator {
+        signal: Signal,
+        amplitude: f64,
+        rate: u32,
+        channels: u8,
+    },
+    // frames recorded with a processor's record setting, played back at their original timing
+    Replay { path: PathBuf },
+}
+
+impl SourceConfig {
+    pub fn to_source_info(&self) -> Result<PaSourceInfo, String> {
+        match *self {
+            SourceConfig::File { ref path } => probe_file(path),
+            SourceConfig::Pipe { ref path, format, rate, channels } => {
+                if rate == 0 || channels == 0 {
+                    return Err(format!("{} needs a nonzero rate and channel count",
+                                       path.display()));
+                }
+                Ok(pipe_source_info(PipeSpec {
+                    path: path.clone(),
+                    format: format,
+                    rate: rate,
+                    channels: channels,
+                }))
+            }
+            SourceConfig::Generator { ref signal, amplitude, rate, channels } => {
+                generator_source_info(GeneratorSpec {
+                    signal: signal.clone(),
+                    amplitude: amplitude,
+                    rate: rate,
+                    channels: channels,
+                })
+            }
+            SourceConfig::Replay { ref path } => probe_recording(path),
+        }
+    }
+}
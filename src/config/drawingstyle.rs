@@ -1,6 +1,7 @@
 use std::cell::RefCell;
 
 use drawing::{Color, DrawingStyle, BarData, GradientData, CircleData};
+use spectrum::FrequencyScale;
 use super::traits::ConvertTo;
 
 #[derive(Serialize, Deserialize)]
@@ -41,6 +42,8 @@ pub struct BarDataConfig {
     pub double_sided: bool,
     pub num_bars: usize,
     pub split_audio_channels: bool,
+    #[serde(default)]
+    pub frequency_scale: FrequencyScale,
     pub max_bar_pieces_vertical: usize,
     pub bar_piece_width: f64,
     pub bar_piece_height: f64,
@@ -60,6 +63,7 @@ impl ConvertTo<BarDataConfig> for BarData {
             double_sided: self.double_sided,
             num_bars: self.num_bars,
             split_audio_channels: self.split_audio_channels,
+            frequency_scale: self.frequency_scale,
             max_bar_pieces_vertical: self.max_bar_pieces_vertical,
             bar_piece_width: self.bar_piece_width,
             bar_piece_height: self.bar_piece_height,
@@ -81,6 +85,7 @@ impl ConvertTo<BarData> for BarDataConfig {
             double_sided: self.double_sided,
             num_bars: self.num_bars,
             split_audio_channels: self.split_audio_channels,
+            frequency_scale: self.frequency_scale,
             max_bar_pieces_vertical: self.max_bar_pieces_vertical,
             bar_piece_width: self.bar_piece_width,
             bar_piece_height: self.bar_piece_height,
@@ -4,15 +4,33 @@ use std::sync::mpsc::Sender;
 use super::traits::ConvertTo;
 use super::drawingstyle::DrawingStyleConfig;
 
-use audio_input::AudioFrame;
+use audio_input::{AudioFrame, PaSourceInfo};
+use spectrum::{AmplitudeSettings, ChannelMapping, FrequencyRange, NormalizerSettings, SmoothingSettings};
 use instance::GtkVisualizerInstance;
 use message::UpdateMessage;
-use shared_data::SharedData;
+use shared_data::{SourceTable, StateHolder};
 
 #[derive(Serialize, Deserialize)]
 pub struct GtkVisualizerConfig {
+    // pulseaudio name of the source, configs from before this only have the index
+    #[serde(default)]
+    pub source: Option<String>,
+    // only used when there is no name to go by
     pub index: usize,
+    // ignore source and index and use whatever the default source is
+    #[serde(default)]
+    pub follow_default: bool,
     pub style: DrawingStyleConfig,
+    #[serde(default)]
+    pub channels: ChannelMapping,
+    #[serde(default)]
+    pub frequency_range: FrequencyRange,
+    #[serde(default)]
+    pub amplitude: AmplitudeSettings,
+    #[serde(default)]
+    pub normalizer: NormalizerSettings,
+    #[serde(default)]
+    pub smoothing: SmoothingSettings,
     pub x_pos: usize,
     pub y_pos: usize,
 }
@@ -20,8 +38,15 @@ pub struct GtkVisualizerConfig {
 impl Default for GtkVisualizerConfig {
     fn default() -> Self {
         GtkVisualizerConfig {
+            source: None,
             index: 0,
+            follow_default: false,
             style: DrawingStyleConfig::default(),
+            channels: ChannelMapping::default(),
+            frequency_range: FrequencyRange::default(),
+            amplitude: AmplitudeSettings::default(),
+            normalizer: NormalizerSettings::default(),
+            smoothing: SmoothingSettings::default(),
             x_pos: 0,
             y_pos: 0,
         }
@@ -31,15 +56,22 @@ impl Default for GtkVisualizerConfig {
 impl GtkVisualizerConfig {
     pub fn to_instance(self,
                    id: usize,
-                   sources: &[SharedData],
+                   sources: StateHolder<SourceTable>,
                    update_sender: Sender<UpdateMessage>)
                    -> GtkVisualizerInstance {
         GtkVisualizerInstance::new_with_style(id,
                                               self.x_pos,
                                               self.y_pos,
+                                              self.source,
                                               self.index,
+                                              self.follow_default,
                                               sources,
                                               self.style.convert_to(),
+                                              self.channels,
+                                              self.frequency_range,
+                                              self.amplitude,
+                                              self.normalizer,
+                                              self.smoothing,
                                               update_sender)
     }
 }
@@ -47,8 +79,15 @@ impl GtkVisualizerConfig {
 impl ConvertTo<GtkVisualizerConfig> for GtkVisualizerInstance {
     fn convert_to(&self) -> GtkVisualizerConfig {
         GtkVisualizerConfig {
+            source: self.source.borrow().clone(),
             index: *self.index.borrow(),
+            follow_default: *self.follow_default.borrow(),
             style: (*self.style).borrow().convert_to(),
+            channels: *self.channels.borrow(),
+            frequency_range: *self.frequency_range.borrow(),
+            amplitude: *self.amplitude.borrow(),
+            normalizer: *self.normalizer.borrow(),
+            smoothing: *self.smoothing.borrow(),
             x_pos: *self.x_pos.borrow(),
             y_pos: *self.y_pos.borrow(),
         }
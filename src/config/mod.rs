@@ -1,10 +1,11 @@
 mod traits;
 mod drawingstyle;
 mod app;
+mod audio;
 mod instance;
 
 use std::fs::{File, create_dir_all};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::io;
 
 use serde_yaml::{from_reader, to_writer};
@@ -12,10 +13,12 @@ use app_dirs::{app_root, AppDataType, AppInfo};
 use app::GtkVisualizerApp;
 
 pub use self::traits::ConvertTo;
+pub use self::audio::{AudioConfig, SourceConfig};
 use self::drawingstyle::DrawingStyleConfig;
 pub use self::instance::GtkVisualizerConfig;
 
 const CONFIG_NAME: &'static str = "visualizers.yml";
+const AUDIO_CONFIG_NAME: &'static str = "audio.yml";
 const CONFIG_DIR: &'static str = "visualizers";
 const APP_INFO: AppInfo = AppInfo { name: "visualizers", author: "Edward Yang" };
 
@@ -27,6 +30,7 @@ lazy_static! {
             Err(_) => panic!("Could not get config file"),
         }
     };
+    static ref AUDIO_CONFIG_PATH: PathBuf = CONFIG_PATH.with_file_name(AUDIO_CONFIG_NAME);
 }
 
 pub fn read_config() -> io::Result<Vec<GtkVisualizerConfig>> {
@@ -47,7 +51,7 @@ pub fn read_config() -> io::Result<Vec<GtkVisualizerConfig>> {
 }
 
 pub fn write_config(config: &Vec<GtkVisualizerConfig>) -> io::Result<()> {
-    let mut config_out = try!(create_config_file());
+    let mut config_out = try!(create_config_file(CONFIG_PATH.as_path()));
     if let Err(e) = to_writer(&mut config_out, &config) {
         Err(io::Error::new(io::ErrorKind::Other,
                            format!("Error on writing config: {}", e)))
@@ -56,7 +60,41 @@ pub fn write_config(config: &Vec<GtkVisualizerConfig>) -> io::Result<()> {
     }
 }
 
-fn create_config_file() -> io::Result<File> {
-    try!(create_dir_all(CONFIG_PATH.parent().unwrap()));
-    File::create(CONFIG_PATH.as_path())
+pub fn read_audio_config() -> io::Result<AudioConfig> {
+    if !AUDIO_CONFIG_PATH.exists() {
+        let def_config = AudioConfig::default();
+        try!(write_audio_config(&def_config));
+        Ok(def_config)
+    } else {
+        let config = try!(File::open(AUDIO_CONFIG_PATH.as_path()));
+        let read: AudioConfig = match from_reader(config) {
+            Ok(read) => read,
+            Err(e) => {
+                return Err(io::Error::new(io::ErrorKind::Other,
+                                          format!("Error on reading audio config: {}", e)))
+            }
+        };
+        match read.processors.validate() {
+            Ok(()) => Ok(read),
+            Err(e) => {
+                Err(io::Error::new(io::ErrorKind::Other,
+                                   format!("Error on reading audio config: {}", e)))
+            }
+        }
+    }
+}
+
+pub fn write_audio_config(config: &AudioConfig) -> io::Result<()> {
+    let mut config_out = try!(create_config_file(AUDIO_CONFIG_PATH.as_path()));
+    if let Err(e) = to_writer(&mut config_out, &config) {
+        Err(io::Error::new(io::ErrorKind::Other,
+                           format!("Error on writing audio config: {}", e)))
+    } else {
+        Ok(())
+    }
+}
+
+fn create_config_file(path: &Path) -> io::Result<File> {
+    try!(create_dir_all(path.parent().unwrap()));
+    File::create(path)
 }
@@ -18,34 +18,65 @@ pub fn shrink_by_averaging(items: &mut Vec<f64>, n: usize) {
         let end = (q * (i + 1) as f64).round() as usize;
         items[i] = average(&items[start..end]);
     }
-    items.split_off(n);
+    items.truncate(n);
 }
 
-pub fn expand_by_clone(items: &mut Vec<f64>, n: usize) -> Vec<f64> {
+// grow vector up to wanted size n by repeating items, in place so a reused vector
+// doesn't need a new allocation
+pub fn expand_by_clone(items: &mut Vec<f64>, n: usize) {
     assert!(n != 0);
     assert!(items.len() < n);
 
-    let clone_times = n / items.len();
-    let mut extra_clone_times = n % items.len();
+    let len = items.len();
+    let clone_times = n / len;
+    let extra_clone_times = n % len;
     let extra_clone_gap;
     if extra_clone_times == 0 {
         extra_clone_gap = 0;
     } else {
-        extra_clone_gap = items.len() / extra_clone_times;
+        extra_clone_gap = len / extra_clone_times;
     }
-    let mut newvec = Vec::with_capacity(n);
-    let mut curidx = 0;
-    for item in items.into_iter().map(|f| *f) {
-        for _ in 0..clone_times {
-            newvec.push(item);
+    items.resize(n, 0.);
+    // fill from the back, every item's clones land at or after where it is now
+    let mut end = n;
+    for curidx in (0..len).rev() {
+        let extra = extra_clone_gap != 0 && curidx % extra_clone_gap == 0 &&
+                    curidx / extra_clone_gap < extra_clone_times;
+        let times = if extra { clone_times + 1 } else { clone_times };
+        let item = items[curidx];
+        for slot in items[end - times..end].iter_mut() {
+            *slot = item;
         }
-        if extra_clone_times > 0 && curidx % extra_clone_gap == 0 {
-            newvec.push(item);
-            extra_clone_times -= 1;
+        end -= times;
+    }
+}
+
+// average of all of the channels, item by item, into out
+pub fn average_channels(data: &[Vec<f64>], out: &mut Vec<f64>) {
+    out.clear();
+    if data.is_empty() {
+        return;
+    }
+    for i in 0..data[0].len() {
+        let mut average = 0.;
+        for datavec in data.iter() {
+            average += datavec[i];
         }
-        curidx += 1;
+        average /= data.len() as f64;
+        out.push(average);
+    }
+}
+
+// shrink or expand to exactly n items, whichever is needed
+pub fn resize_to(items: &mut Vec<f64>, n: usize) {
+    if items.is_empty() {
+        // nothing to stretch
+        items.resize(n, 0.);
+    } else if items.len() > n {
+        shrink_by_averaging(items, n);
+    } else if items.len() < n {
+        expand_by_clone(items, n);
     }
-    newvec
 }
 
 pub fn scale_to_maximum(items: &mut Vec<f64>, maximum: f64) {
@@ -103,17 +134,33 @@ fn test_shrink_by_averaging_5() {
 #[test]
 fn test_expand_by_clone_1() {
     let mut data = vec![0., 1., 2.];
-    data = expand_by_clone(&mut data, 5);
+    expand_by_clone(&mut data, 5);
     assert_eq!(data.as_slice(), &[0., 0., 1., 1., 2.]);
 }
 
 #[test]
 fn test_expand_by_clone_2() {
     let mut data = vec![0., 1., 2.];
-    data = expand_by_clone(&mut data, 6);
+    expand_by_clone(&mut data, 6);
     assert_eq!(data.as_slice(), &[0., 0., 1., 1., 2., 2.]);
 }
 
+#[test]
+fn test_resize_to() {
+    let mut data = vec![0., 1., 2.];
+    resize_to(&mut data, 5);
+    assert_eq!(data.as_slice(), &[0., 0., 1., 1., 2.]);
+    resize_to(&mut data, 5);
+    assert_eq!(data.len(), 5);
+    resize_to(&mut data, 2);
+    assert_eq!(data.as_slice(), &[1. / 3., 1.5]);
+    let mut empty = vec![1.];
+    average_channels(&[], &mut empty);
+    assert!(empty.is_empty());
+    resize_to(&mut empty, 2);
+    assert_eq!(empty.as_slice(), &[0., 0.]);
+}
+
 pub fn scale(items: &mut Vec<f64>) {
     // https://www.reddit.com/r/rust/comments/3fg0xr/how_do_i_find_the_max_value_in_a_vecf64/
     let maximum = items.iter().cloned().fold(::std::f64::NAN, f64::max);
@@ -141,6 +188,15 @@ fn test_scale_3() {
     assert_eq!(data.as_slice(), &[0., 0., 0.]);
 }
 
+// index of the largest item, the first one on a tie
+#[cfg(test)]
+pub fn peak_index(items: &[f64]) -> usize {
+    items.iter()
+        .enumerate()
+        .fold((0, ::std::f64::NEG_INFINITY), |max, (i, &x)| if x > max.1 { (i, x) } else { max })
+        .0
+}
+
 #[test]
 fn test_map_multiply() {
     let mut data = vec![1., 2., 3.];
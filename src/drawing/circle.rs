@@ -1,11 +1,15 @@
 use cairo::Context;
-use super::Draw;
+use super::{Bands, Draw, map_channels};
+use audio_input::AudioFrame;
 use super::color::Color;
-use data_helpers::{shrink_by_averaging, scale};
+use data_helpers::scale;
+use spectrum::{FrequencyRange, FrequencyScale, map_frequencies};
 
 #[derive(Clone, Serialize, Deserialize)]
 pub struct CircleData {
     pub split_audio_channels: bool,
+    #[serde(default)]
+    pub frequency_scale: FrequencyScale,
     pub min_radius: f64,
     pub max_radius: f64,
     pub draw_color: Color,
@@ -21,6 +25,7 @@ impl Default for CircleData {
     fn default() -> Self {
         CircleData {
             split_audio_channels: false,
+            frequency_scale: FrequencyScale::Linear,
             min_radius: 70.,
             max_radius: 200.,
             draw_color: Color::black(),
@@ -35,7 +40,15 @@ impl Default for CircleData {
 }
 
 impl Draw for CircleData {
-    fn draw(&self, context: &Context, data: &mut Vec<Vec<f64>>) {
+    fn bands(&self, frame: &AudioFrame, range: FrequencyRange, out: &mut Bands) {
+        // one point every 2 degrees all the way around
+        let points = if self.split_audio_channels { 90 } else { 180 };
+        map_channels(frame, self.split_audio_channels, out, |data, mapped| {
+            map_frequencies(data, frame.rate, self.frequency_scale, range, points, mapped)
+        });
+    }
+
+    fn draw(&self, context: &Context, bands: &mut [Vec<f64>], _frame: &AudioFrame) {
         context.save();
         context.translate(self.left_padding, self.top_padding);
         // draw the background
@@ -46,48 +59,29 @@ impl Draw for CircleData {
 
         let max_increase = self.max_radius - self.min_radius;
 
-        let mut combined;
-        if self.split_audio_channels {
-            for datavec in data.iter_mut() {
-                shrink_by_averaging(datavec, 90);
-            }
-            combined = Vec::with_capacity(180);
-            combined.extend(data[0].iter());
-            combined.extend(data[1].iter().rev());
-        } else {
-            combined = Vec::with_capacity(256);
-            for i in 0..data[0].len() {
-                let mut average = 0.;
-                for datavec in data.iter() {
-                    average += datavec[i];
-                }
-                average /= data.len() as f64;
-                combined.push(average);
-            }
-            shrink_by_averaging(&mut combined, 180);
-        }
+        // split channels go around one way, then back the other
+        let combined = bands[0]
+            .iter()
+            .chain(bands.iter().skip(1).flat_map(|channel| channel.iter().rev()));
         // scale(&mut combined);
 
-        let mut points = Vec::new();
         let rotation_angle = match self.rotation {
             None => 0.,
             Some(angle) => angle,
         };
 
-        for (angle, percentage) in combined.into_iter()
-            .enumerate()
+        context.translate(self.max_radius, self.max_radius);
+        for (angle, &percentage) in combined.enumerate()
             .map(|(a, p)| (a as f64 * 2., p)) {
             let radian = to_radians(angle as f64 + rotation_angle);
             let actual_radius = self.min_radius + (max_increase * percentage);
             let x = actual_radius * radian.cos();
             let y = actual_radius * radian.sin();
-            points.push((x, y));
-        }
-
-        context.translate(self.max_radius, self.max_radius);
-        context.move_to(points[0].0, points[0].1);
-        for &(x, y) in points.iter().skip(1) {
-            context.line_to(x, y);
+            if angle == 0. {
+                context.move_to(x, y);
+            } else {
+                context.line_to(x, y);
+            }
         }
         context.close_path();
         context.stroke();
@@ -1,13 +1,17 @@
 use cairo::{Context, LinearGradient};
 use cairo::prelude::*;
 
-use super::Draw;
-use data_helpers::{scale, map_multiply, shrink_by_averaging};
+use super::{Bands, Draw, map_channels};
+use audio_input::AudioFrame;
+use data_helpers::{scale, map_multiply};
+use spectrum::{FrequencyRange, FrequencyScale, map_frequencies};
 use super::color::Color;
 
 #[derive(Clone, Serialize, Deserialize)]
 pub struct GradientData {
     pub split_audio_channels: bool,
+    #[serde(default)]
+    pub frequency_scale: FrequencyScale,
     pub height: f64,
     pub width: f64,
     pub middle_line_height: f64,
@@ -26,6 +30,7 @@ impl Default for GradientData {
     fn default() -> Self {
         GradientData {
             split_audio_channels: true,
+            frequency_scale: FrequencyScale::Linear,
             height: 80.,
             width: 1200.,
             middle_line_height: 2.,
@@ -43,7 +48,20 @@ impl Default for GradientData {
 }
 
 impl Draw for GradientData {
-    fn draw(&self, context: &Context, data: &mut Vec<Vec<f64>>) {
+    fn bands(&self, frame: &AudioFrame, range: FrequencyRange, out: &mut Bands) {
+        map_channels(frame, self.split_audio_channels, out, |datavec, mapped| {
+            // keep enough points around for the curves
+            let mut target = datavec.len()
+                .checked_shr(self.width_desensitivity as u32)
+                .unwrap_or(0);
+            if target < 4 {
+                target = datavec.len();
+            }
+            map_frequencies(datavec, frame.rate, self.frequency_scale, range, target, mapped)
+        });
+    }
+
+    fn draw(&self, context: &Context, bands: &mut [Vec<f64>], _frame: &AudioFrame) {
         context.save();
         context.translate(self.left_padding, self.top_padding);
         let gradient = LinearGradient::new(0., 0., self.width, 0.);
@@ -55,13 +73,7 @@ impl Draw for GradientData {
 
         let half_height = (self.height - self.middle_line_height) / 2.;
 
-        for mut datavec in data.iter_mut() {
-            // scale(datavec); // replace this with a more absolute scale later on
-            if self.width_desensitivity != 0 {
-                shrink_by_averaging(&mut datavec,
-                                    256 / (2usize.pow(self.width_desensitivity as u32)));
-                // replace 256 with FRAMES
-            }
+        for datavec in bands.iter_mut() {
             datavec.pop();
             {
                 let tmplen = datavec.len();
@@ -83,82 +95,75 @@ impl Draw for GradientData {
         context.translate(0., -self.middle_line_height / 2.);
         // bezier curve fun
         call_rgba_fn!(context, set_source_rgba, self.bg_color);
-        let scale_x;
-        let combined;
-        let draw_half: Box<Fn()>;
         if self.split_audio_channels {
-            scale_x = self.width / (data.len() * data[0].len()) as f64;
-            combined = {
-                let mut v = Vec::with_capacity(data.len() * data[0].len());
-                v.extend(data[0].iter());
-                v.extend(data[1].iter().rev());
-                v
-            };
-            draw_half = Box::new(move || {
-                let (p1, p2) = combined.split_at(combined.len() / 2);
-                context.move_to(0., combined[0]);
-                for (idx, chunk) in p1.chunks(4)
-                    .enumerate()
-                    .map(|(idx, c)| (idx * 4 + 1, c)) {
-                    let (x1, y1) = ((idx + 1) as f64 * scale_x, (self.height / 2.) - chunk[1]);
-                    let (x2, y2) = ((idx + 2) as f64 * scale_x, (self.height / 2.) - chunk[2]);
-                    let (x3, y3) = ((idx + 3) as f64 * scale_x, (self.height / 2.) - chunk[3]);
+            // the right channel comes back from the far end
+            bands[1].reverse();
+        }
+        let data: &[Vec<f64>] = bands;
+        let draw_split = || {
+            let scale_x = self.width / (data.len() * data[0].len()) as f64;
+            let (p1, p2) = (&data[0], &data[1]);
+            let combined_len = p1.len() + p2.len();
+            context.move_to(0., p1[0]);
+            for (idx, chunk) in p1.chunks(4)
+                .enumerate()
+                .map(|(idx, c)| (idx * 4 + 1, c)) {
+                let (x1, y1) = ((idx + 1) as f64 * scale_x, (self.height / 2.) - chunk[1]);
+                let (x2, y2) = ((idx + 2) as f64 * scale_x, (self.height / 2.) - chunk[2]);
+                let (x3, y3) = ((idx + 3) as f64 * scale_x, (self.height / 2.) - chunk[3]);
+                context.curve_to(x1,
+                                 y1,
+                                 x2,
+                                 y2,
+                                 f64::min(self.width / 2., x3),
+                                 f64::min(y3, half_height));
+            }
+            for (idx, chunk) in p2.chunks(4)
+                .enumerate()
+                .map(|(idx, c)| (idx * 4 + 1 + combined_len / 2, c)) {
+                let (x1, y1) = ((idx + 1) as f64 * scale_x, (self.height / 2.) - chunk[1]);
+                let (x2, y2) = ((idx + 2) as f64 * scale_x, (self.height / 2.) - chunk[2]);
+                let (x3, y3) = ((idx + 3) as f64 * scale_x, (self.height / 2.) - chunk[3]);
+                if idx == combined_len - 3 {
+                    context.curve_to(x1, y1, x2, y2, f64::min(self.width, x3), half_height);
+                } else {
                     context.curve_to(x1,
                                      y1,
                                      x2,
                                      y2,
-                                     f64::min(self.width / 2., x3),
+                                     f64::min(self.width, x3),
                                      f64::min(y3, half_height));
                 }
-                for (idx, chunk) in p2.chunks(4)
-                    .enumerate()
-                    .map(|(idx, c)| (idx * 4 + 1 + combined.len() / 2, c)) {
-                    let (x1, y1) = ((idx + 1) as f64 * scale_x, (self.height / 2.) - chunk[1]);
-                    let (x2, y2) = ((idx + 2) as f64 * scale_x, (self.height / 2.) - chunk[2]);
-                    let (x3, y3) = ((idx + 3) as f64 * scale_x, (self.height / 2.) - chunk[3]);
-                    if idx == combined.len() - 3 {
-                        context.curve_to(x1, y1, x2, y2, f64::min(self.width, x3), half_height);
-                    } else {
-                        context.curve_to(x1,
-                                         y1,
-                                         x2,
-                                         y2,
-                                         f64::min(self.width, x3),
-                                         f64::min(y3, half_height));
-                    }
-                }
-                context.line_to(self.width, 0.);
-                context.line_to(0., 0.);
-                context.line_to(0., half_height);
-                context.close_path();
-                context.fill();
-            });
+            }
+            context.line_to(self.width, 0.);
+            context.line_to(0., 0.);
+            context.line_to(0., half_height);
+            context.close_path();
+            context.fill();
+        };
+        // bands() already averaged the channels
+        let draw_averaged = || {
+            let scale_x = self.width / data[0].len() as f64;
+            context.move_to(0., data[0][0]);
+            for (idx, chunk) in data[0].chunks(4)
+                .enumerate()
+                .map(|(idx, c)| (idx * 4 + 1, c)) {
+                let (x1, y1) = ((idx + 1) as f64 * scale_x, (self.height / 2.) - chunk[1]);
+                let (x2, y2) = ((idx + 2) as f64 * scale_x, (self.height / 2.) - chunk[2]);
+                let (x3, y3) = ((idx + 3) as f64 * scale_x, (self.height / 2.) - chunk[3]);
+                context.curve_to(x1, y1, x2, y2, f64::min(self.width, x3), y3);
+            }
+            context.line_to(self.width, 0.);
+            context.line_to(0., 0.);
+            context.line_to(0., half_height);
+            context.close_path();
+            context.fill();
+        };
+        let draw_half: &Fn() = if self.split_audio_channels {
+            &draw_split
         } else {
-            scale_x = self.width / data[0].len() as f64;
-            combined = {
-                let mut v = Vec::with_capacity(data[0].len());
-                for i in 0..data[0].len() {
-                    v.push((data[0][i] + data[1][i]) / 2.);
-                }
-                v
-            };
-            draw_half = Box::new(move || {
-                context.move_to(0., combined[0]);
-                for (idx, chunk) in combined.chunks(4)
-                    .enumerate()
-                    .map(|(idx, c)| (idx * 4 + 1, c)) {
-                    let (x1, y1) = ((idx + 1) as f64 * scale_x, (self.height / 2.) - chunk[1]);
-                    let (x2, y2) = ((idx + 2) as f64 * scale_x, (self.height / 2.) - chunk[2]);
-                    let (x3, y3) = ((idx + 3) as f64 * scale_x, (self.height / 2.) - chunk[3]);
-                    context.curve_to(x1, y1, x2, y2, f64::min(self.width, x3), y3);
-                }
-                context.line_to(self.width, 0.);
-                context.line_to(0., 0.);
-                context.line_to(0., half_height);
-                context.close_path();
-                context.fill();
-            });
-        }
+            &draw_averaged
+        };
         // upper half
         draw_half();
         context.restore();
@@ -8,6 +8,10 @@ mod style;
 
 use cairo::{Context, Operator};
 
+use audio_input::{AudioFrame, FrameContent};
+use data_helpers::average_channels;
+use spectrum::FrequencyRange;
+
 pub use self::color::Color;
 pub use self::bar::BarData;
 pub use self::circle::CircleData;
@@ -15,6 +19,84 @@ pub use self::gradient::GradientData;
 pub use self::style::DrawingStyle;
 
 pub trait Draw {
-    fn draw(&self, context: &Context, data: &mut Vec<Vec<f64>>);
+    // what the frames handed to bands() and draw() need to carry
+    fn content(&self) -> FrameContent {
+        FrameContent::spectrum()
+    }
+    // reduce the part of the frame's spectrum within range down to the values that get drawn,
+    // one vec per drawn channel in out.values
+    fn bands(&self, frame: &AudioFrame, range: FrequencyRange, out: &mut Bands);
+    // draw the values from bands() once they have been smoothed, styles that asked for the
+    // waveform find it in frame.samples. bands is empty if the spectrum wasn't asked for,
+    // and can be changed in place since it gets refilled for every frame
+    fn draw(&self, context: &Context, bands: &mut [Vec<f64>], frame: &AudioFrame);
     fn draw_area(&self) -> (f64, f64);
 }
+
+// what bands() fills, an instance keeps one around so drawing a frame doesn't allocate
+#[derive(Default)]
+pub struct Bands {
+    pub values: Vec<Vec<f64>>,
+    // all of the channels averaged into one, before mapping
+    averaged: Vec<f64>,
+}
+
+// map the left and right channel into their own vec, or the average of every channel into one
+fn map_channels<F>(frame: &AudioFrame, split: bool, out: &mut Bands, map: F)
+    where F: Fn(&[f64], &mut Vec<f64>)
+{
+    let Bands { ref mut values, ref mut averaged } = *out;
+    if split {
+        let (left, right) = frame.stereo();
+        values.resize(2, Vec::new());
+        map(left, &mut values[0]);
+        map(right, &mut values[1]);
+    } else {
+        average_channels(&frame.magnitudes, averaged);
+        values.resize(1, Vec::new());
+        map(averaged, &mut values[0]);
+    }
+}
+
+// shown instead of the visualization when there is nothing to draw
+pub fn draw_status(context: &Context, area: (f64, f64), text: &str) {
+    context.save();
+    context.set_operator(Operator::Source);
+    context.set_source_rgba(0., 0., 0., 0.5);
+    context.paint();
+    context.set_operator(Operator::Over);
+    context.set_source_rgba(1., 1., 1., 0.9);
+    context.set_font_size(14.);
+    context.move_to(10., area.1 / 2.);
+    context.show_text(text);
+    context.restore();
+}
+
+// mapping a stereo 2048 point frame down to what gets drawn, like every draw does
+#[cfg(test)]
+mod benches {
+    use test::Bencher;
+    use audio_input::AudioFrame;
+    use spectrum::{FrequencyRange, FrequencyScale};
+    use super::{BarData, Bands, GradientData, Draw};
+
+    fn frame() -> AudioFrame {
+        AudioFrame { magnitudes: vec![vec![0.5; 2048]; 2], rate: 44100, ..AudioFrame::default() }
+    }
+
+    #[bench]
+    fn bench_bar_bands(b: &mut Bencher) {
+        let bars = BarData { frequency_scale: FrequencyScale::Octave(3), ..BarData::default() };
+        let frame = frame();
+        let mut out = Bands::default();
+        b.iter(|| bars.bands(&frame, FrequencyRange::default(), &mut out));
+    }
+
+    #[bench]
+    fn bench_gradient_bands(b: &mut Bencher) {
+        let gradient = GradientData::default();
+        let frame = frame();
+        let mut out = Bands::default();
+        b.iter(|| gradient.bands(&frame, FrequencyRange::default(), &mut out));
+    }
+}
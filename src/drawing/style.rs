@@ -1,5 +1,7 @@
 use cairo::{Context, Operator};
-use super::{BarData, CircleData, GradientData, Draw};
+use super::{BarData, Bands, CircleData, GradientData, Draw};
+use audio_input::{AudioFrame, FrameContent};
+use spectrum::FrequencyRange;
 
 pub enum DrawingStyle {
     Bars(BarData),
@@ -14,12 +16,28 @@ impl Default for DrawingStyle {
 }
 
 impl Draw for DrawingStyle {
-    fn draw(&self, context: &Context, data: &mut Vec<Vec<f64>>) {
+    fn content(&self) -> FrameContent {
+        match *self {
+            DrawingStyle::Bars(ref bardata) => bardata.content(),
+            DrawingStyle::Circle(ref circledata) => circledata.content(),
+            DrawingStyle::Gradient(ref kuwodata) => kuwodata.content(),
+        }
+    }
+
+    fn bands(&self, frame: &AudioFrame, range: FrequencyRange, out: &mut Bands) {
+        match *self {
+            DrawingStyle::Bars(ref bardata) => bardata.bands(frame, range, out),
+            DrawingStyle::Circle(ref circledata) => circledata.bands(frame, range, out),
+            DrawingStyle::Gradient(ref kuwodata) => kuwodata.bands(frame, range, out),
+        }
+    }
+
+    fn draw(&self, context: &Context, bands: &mut [Vec<f64>], frame: &AudioFrame) {
         context.set_operator(Operator::Source);
         match *self {
-            DrawingStyle::Bars(ref bardata) => bardata.draw(context, data),
-            DrawingStyle::Circle(ref circledata) => circledata.draw(context, data),
-            DrawingStyle::Gradient(ref kuwodata) => kuwodata.draw(context, data),
+            DrawingStyle::Bars(ref bardata) => bardata.draw(context, bands, frame),
+            DrawingStyle::Circle(ref circledata) => circledata.draw(context, bands, frame),
+            DrawingStyle::Gradient(ref kuwodata) => kuwodata.draw(context, bands, frame),
         }
     }
 
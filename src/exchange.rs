@@ -0,0 +1,266 @@
+use std::cell::{Cell, UnsafeCell};
+use std::marker::PhantomData;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use audio_input::{AudioFrame, FrameSummary};
+
+// set in `middle` when the writer has put a frame there the reader hasn't picked up yet
+const FRESH: usize = 0b100;
+const INDEX_MASK: usize = 0b011;
+
+struct Slot {
+    // false means no signal
+    valid: bool,
+    frame: AudioFrame,
+}
+
+// Hands the latest frame from a capture worker to the draw callbacks without locking.
+// Triple buffer: the writer owns one buffer, the reader owns another and the third sits in
+// the middle. Publishing and picking up are a single atomic swap of the middle buffer, so
+// neither side ever waits on the other, and frames are copied into buffers that keep their
+// allocations.
+struct FrameExchange {
+    slots: [UnsafeCell<Slot>; 3],
+    middle: AtomicUsize,
+    write_index: UnsafeCell<usize>,
+    read_index: UnsafeCell<usize>,
+}
+
+// only ever touched through the one publisher and the one receiver
+unsafe impl Sync for FrameExchange {}
+unsafe impl Send for FrameExchange {}
+
+// The writing end, there is exactly one per exchange. Every capture worker gets an exchange
+// of its own, so a worker that is still shutting down can't write over its successor.
+pub struct FramePublisher {
+    exchange: Arc<FrameExchange>,
+}
+
+// The reading end, it can move to the gtk thread but not be shared between threads.
+pub struct FrameReceiver {
+    exchange: Arc<FrameExchange>,
+    not_sync: PhantomData<Cell<()>>,
+}
+
+pub fn frame_exchange() -> (FramePublisher, FrameReceiver) {
+    let exchange = Arc::new(FrameExchange {
+        slots: [UnsafeCell::new(Slot::empty()),
+                UnsafeCell::new(Slot::empty()),
+                UnsafeCell::new(Slot::empty())],
+        middle: AtomicUsize::new(1),
+        write_index: UnsafeCell::new(0),
+        read_index: UnsafeCell::new(2),
+    });
+    (FramePublisher { exchange: exchange.clone() },
+     FrameReceiver {
+        exchange: exchange,
+        not_sync: PhantomData,
+    })
+}
+
+impl FramePublisher {
+    // publish a new frame
+    pub fn write(&mut self, frame: &AudioFrame) {
+        self.publish(|slot| {
+            slot.valid = true;
+            slot.frame.clone_from(frame);
+        });
+    }
+
+    // publish that there is no signal
+    pub fn clear(&mut self) {
+        self.publish(|slot| slot.valid = false);
+    }
+
+    fn publish<F: FnOnce(&mut Slot)>(&mut self, fill: F) {
+        let exchange = &*self.exchange;
+        unsafe {
+            let write_index = &mut *exchange.write_index.get();
+            fill(&mut *exchange.slots[*write_index].get());
+            *write_index = exchange.middle.swap(*write_index | FRESH, Ordering::AcqRel) & INDEX_MASK;
+        }
+    }
+}
+
+impl FrameReceiver {
+    // the receiving end of an exchange nothing writes to, for sources nobody captures
+    pub fn empty() -> Self {
+        frame_exchange().1
+    }
+
+    // copy the latest frame into out, reusing its allocation. false if there is no signal
+    pub fn read_into(&self, out: &mut AudioFrame) -> bool {
+        let slot = self.latest();
+        if slot.valid {
+            out.clone_from(&slot.frame);
+        }
+        slot.valid
+    }
+
+    // just the summary of the latest frame, without copying the spectra
+    pub fn read_summary(&self) -> Option<FrameSummary> {
+        let slot = self.latest();
+        if slot.valid {
+            Some(slot.frame.summary())
+        } else {
+            None
+        }
+    }
+
+    // pick up the frame in the middle if it is new. the slot stays the reader's until the next call
+    fn latest(&self) -> &Slot {
+        let exchange = &*self.exchange;
+        unsafe {
+            let read_index = &mut *exchange.read_index.get();
+            if exchange.middle.load(Ordering::Acquire) & FRESH != 0 {
+                *read_index = exchange.middle.swap(*read_index, Ordering::AcqRel) & INDEX_MASK;
+            }
+            &*exchange.slots[*read_index].get()
+        }
+    }
+}
+
+impl Slot {
+    fn empty() -> Self {
+        Slot {
+            valid: false,
+            frame: AudioFrame::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+fn frame_of(magnitudes: Vec<Vec<f64>>) -> AudioFrame {
+    AudioFrame { magnitudes: magnitudes, ..AudioFrame::default() }
+}
+
+#[test]
+fn test_exchange_latest_frame() {
+    let (mut exchange, receiver) = frame_exchange();
+    let mut out = frame_of(vec![vec![9.]]);
+    assert!(!receiver.read_into(&mut out));
+    assert_eq!(out.magnitudes, vec![vec![9.]]);
+    exchange.write(&frame_of(vec![vec![1., 2.]]));
+    exchange.write(&frame_of(vec![vec![3., 4.]]));
+    assert!(receiver.read_into(&mut out));
+    assert_eq!(out.magnitudes, vec![vec![3., 4.]]);
+    // nothing new, the same frame again
+    assert!(receiver.read_into(&mut out));
+    assert_eq!(out.magnitudes, vec![vec![3., 4.]]);
+    exchange.clear();
+    assert!(!receiver.read_into(&mut out));
+    assert_eq!(receiver.read_summary(), None);
+    exchange.write(&frame_of(vec![vec![5.], vec![6.]]));
+    assert!(receiver.read_into(&mut out));
+    assert_eq!(out.magnitudes, vec![vec![5.], vec![6.]]);
+    exchange.write(&AudioFrame { bpm: Some(120.), ..AudioFrame::default() });
+    assert_eq!(receiver.read_summary().and_then(|s| s.bpm), Some(120.));
+}
+
+#[test]
+fn test_exchange_threads() {
+    use std::thread;
+    let (mut exchange, receiver) = frame_exchange();
+    let writer = thread::spawn(move || for i in 0..10000 {
+        exchange.write(&AudioFrame {
+            magnitudes: vec![vec![i as f64; 64]; 2],
+            sequence: i,
+            ..AudioFrame::default()
+        });
+    });
+    let mut out = AudioFrame::default();
+    let mut last = 0;
+    for _ in 0..10000 {
+        if receiver.read_into(&mut out) {
+            // a frame is never torn and never goes back in time
+            let sequence = out.sequence as f64;
+            assert!(out.magnitudes.iter().all(|c| c.iter().all(|&v| v == sequence)));
+            assert!(out.sequence >= last);
+            last = out.sequence;
+        }
+    }
+    writer.join().unwrap();
+    assert!(receiver.read_into(&mut out));
+    assert_eq!(out.sequence, 9999);
+}
+
+// each side of the exchange while another thread hammers the other side, against the old
+// Arc<Mutex<Option<Vec<Vec<f64>>>>> that got cloned on every draw
+#[cfg(test)]
+mod benches {
+    use std::sync::{Arc, Mutex};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::thread;
+    use test::Bencher;
+    use super::{frame_exchange, frame_of};
+
+    fn frame() -> Vec<Vec<f64>> {
+        vec![vec![0.5; 2048]; 2]
+    }
+
+    // run other in a loop on its own thread while benching this
+    fn contended<F, G>(b: &mut Bencher, mut this: F, mut other: G)
+        where F: FnMut(),
+              G: FnMut() + Send + 'static
+    {
+        let stop = Arc::new(AtomicBool::new(false));
+        let handle = {
+            let stop = stop.clone();
+            thread::spawn(move || while !stop.load(Ordering::Relaxed) {
+                other();
+            })
+        };
+        b.iter(|| this());
+        stop.store(true, Ordering::Relaxed);
+        handle.join().unwrap();
+    }
+
+    #[bench]
+    fn bench_mutex_read(b: &mut Bencher) {
+        let shared = Arc::new(Mutex::new(Some(frame())));
+        let writer = shared.clone();
+        let data = frame();
+        contended(b,
+                  || {
+                      shared.lock().unwrap().clone();
+                  },
+                  move || *writer.lock().unwrap() = Some(data.clone()));
+    }
+
+    #[bench]
+    fn bench_exchange_read(b: &mut Bencher) {
+        let (mut writer, receiver) = frame_exchange();
+        let data = frame_of(frame());
+        let mut out = frame_of(frame());
+        contended(b,
+                  || {
+                      receiver.read_into(&mut out);
+                  },
+                  move || writer.write(&data));
+    }
+
+    #[bench]
+    fn bench_mutex_write(b: &mut Bencher) {
+        let shared = Arc::new(Mutex::new(Some(frame())));
+        let reader = shared.clone();
+        let data = frame();
+        contended(b,
+                  || *shared.lock().unwrap() = Some(data.clone()),
+                  move || {
+                      reader.lock().unwrap().clone();
+                  });
+    }
+
+    #[bench]
+    fn bench_exchange_write(b: &mut Bencher) {
+        let (mut exchange, reader) = frame_exchange();
+        let data = frame_of(frame());
+        let mut out = frame_of(frame());
+        contended(b,
+                  || exchange.write(&data),
+                  move || {
+                      reader.read_into(&mut out);
+                  });
+    }
+}
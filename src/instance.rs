@@ -10,11 +10,13 @@ use std::cell::RefCell;
 use std::sync::mpsc::{channel, Sender};
 use std::sync::{Arc, Mutex};
 
-use audio_input::AudioFrame;
+use audio_input::{AudioFrame, PaSourceInfo};
 use drawing::*;
+use drawing::draw_status;
+use spectrum::{scale_levels, to_full_scale, AmplitudeSettings, ChannelMapping, FrequencyRange, Normalizer, NormalizerSettings, Smoother, SmoothingSettings};
 use ui::{is_right_click, SettingsWindow};
 use message::UpdateMessage;
-use shared_data::{SharedData, StateHolder};
+use shared_data::{SourceTable, StateHolder};
 
 // make this changeable in program settings later on: Arc<Mutex> for each instance
 const DRAW_UPDATE_TIME: u64 = 1000_000_000; // ns or 500 ms
@@ -23,13 +25,21 @@ const DRAW_UPDATE_TIME: u64 = 1000_000_000; // ns or 500 ms
 // have instance have a Arc<Mutex<DrawingStyle>> and just mutate that
 pub struct GtkVisualizerInstance {
     id: usize,
+    // name of the source this instance wants, it may not be plugged in right now
+    pub source: StateHolder<Option<String>>,
     pub index: StateHolder<usize>,
+    pub follow_default: StateHolder<bool>,
     window: Window,
     pub x_pos: StateHolder<usize>,
     pub y_pos: StateHolder<usize>,
     pub style: StateHolder<DrawingStyle>,
+    pub channels: StateHolder<ChannelMapping>,
+    pub frequency_range: StateHolder<FrequencyRange>,
+    pub amplitude: StateHolder<AmplitudeSettings>,
+    pub normalizer: StateHolder<NormalizerSettings>,
+    pub smoothing: StateHolder<SmoothingSettings>,
     msg_sender: Sender<UpdateMessage>,
-    data_sources: Vec<SharedData>,
+    sources: StateHolder<SourceTable>,
     last_drawn: u64,
     instance_continue: StateHolder<bool>,
 }
@@ -39,22 +49,69 @@ impl GtkVisualizerInstance {
                x: usize,
                y: usize,
                index: usize,
-               sources: &[SharedData],
+               sources: StateHolder<SourceTable>,
                update_sender: Sender<UpdateMessage>)
                -> Self {
         let style = DrawingStyle::default();
-        Self::new_with_style(id, x, y, index, sources, style, update_sender)
+        Self::new_with_style(id,
+                             x,
+                             y,
+                             None,
+                             index,
+                             false,
+                             sources,
+                             style,
+                             ChannelMapping::default(),
+                             FrequencyRange::default(),
+                             AmplitudeSettings::default(),
+                             NormalizerSettings::default(),
+                             SmoothingSettings::default(),
+                             update_sender)
     }
 
     pub fn new_with_style(id: usize,
                           x: usize,
                           y: usize,
+                          source: Option<String>,
                           index: usize,
-                          sources: &[SharedData],
+                          follow_default: bool,
+                          sources: StateHolder<SourceTable>,
                           style: DrawingStyle,
+                          channels: ChannelMapping,
+                          frequency_range: FrequencyRange,
+                          amplitude: AmplitudeSettings,
+                          normalizer: NormalizerSettings,
+                          smoothing: SmoothingSettings,
                           update_sender: Sender<UpdateMessage>)
                           -> Self {
-        update_sender.send(UpdateMessage::Add(id, index)).unwrap();
+        // find the source by name, configs without one fall back to the index
+        let (source, index) = {
+            let table = sources.borrow();
+            let found = match (table.default_index, source.as_ref()) {
+                (Some(default_index), _) if follow_default => Some(default_index),
+                (_, Some(name)) => table.find(name),
+                (_, None) => table.get(index).map(|_| index),
+            };
+            match found {
+                Some(found) => {
+                    let name = table.get(found).map(|(_, info)| info.name.clone());
+                    (name.or(source), found)
+                }
+                // wait for it to be plugged in
+                None => (source, index),
+            }
+        };
+        let attached = match (source.as_ref(), sources.borrow().get(index)) {
+            (Some(name), Some((_, info))) => *name == info.name,
+            _ => false,
+        };
+        if attached {
+            update_sender.send(UpdateMessage::Add(id, index)).unwrap();
+        }
+        update_sender.send(UpdateMessage::Content(id, style.content().union(channels.content()))).unwrap();
+        if follow_default {
+            update_sender.send(UpdateMessage::FollowDefault(id, true)).unwrap();
+        }
         let window = Window::new(WindowType::Toplevel);
 
         window.set_title(&format!("Visualizers Instance {}", id));
@@ -76,16 +133,28 @@ impl GtkVisualizerInstance {
             panic!("Cannot use non-composited screen");
         }
 
+        let source = Rc::new(RefCell::new(source));
         let index = Rc::new(RefCell::new(index));
+        let follow_default = Rc::new(RefCell::new(follow_default));
         let x_pos = Rc::new(RefCell::new(x));
         let y_pos = Rc::new(RefCell::new(y));
         let style = Rc::new(RefCell::new(style));
+        let channels = Rc::new(RefCell::new(channels));
+        let frequency_range = Rc::new(RefCell::new(frequency_range));
+        let amplitude = Rc::new(RefCell::new(amplitude));
+        let normalizer = Rc::new(RefCell::new(normalizer));
+        let smoothing = Rc::new(RefCell::new(smoothing));
         let instance_continue = Rc::new(RefCell::new(true));
-        let sources = sources.to_vec();
 
         // Setup draw operations
         {
-            clone_local!(index, x_pos, y_pos, style, sources);
+            clone_local!(source, index, x_pos, y_pos, style, channels, frequency_range, amplitude, normalizer, smoothing, sources);
+            let normalizer_state = RefCell::new(Normalizer::new());
+            let smoother = RefCell::new(Smoother::new());
+            // the latest frame gets copied in here, keeping the allocation between draws
+            let frame = RefCell::new(AudioFrame::default());
+            // and mapped down to what gets drawn in here
+            let bands = RefCell::new(Bands::default());
             window.connect_draw(move |window, context| {
                 {
                     // resize to the needed draw size
@@ -93,15 +162,41 @@ impl GtkVisualizerInstance {
                     let (width, height) = style.draw_area();
                     window.resize(width as i32, height as i32);
                     // get the source data
-                    let item = &sources[*index.borrow()];
-                    let mut unwrapped = item.lock().unwrap().clone();
-                    match unwrapped {
-                        Some(ref mut source) => {
-                            // draw it
-                            style.draw(context, source);
+                    let index = *index.borrow();
+                    let mut frame = frame.borrow_mut();
+                    // the source may have been unplugged, or something else took its index
+                    let status = match (sources.borrow().get(index), source.borrow().as_ref()) {
+                        (Some((data, info)), Some(name)) if info.name == *name => {
+                            if data.read_into(&mut frame) {
+                                // the mapping may not fit the source's channels
+                                channels.borrow().apply(&mut frame, &info.positions)
+                            } else {
+                                // the source is failing and waiting to be retried, or not opened yet
+                                Err("No signal".to_string())
+                            }
+                        }
+                        _ => Err("Source missing".to_string()),
+                    };
+                    match status {
+                        Err(e) => draw_status(context, (width, height), &e),
+                        Ok(()) => {
+                            let now = precise_time_ns();
+                            let mut bands = bands.borrow_mut();
+                            if style.content().spectrum {
+                                to_full_scale(&mut frame.magnitudes);
+                                style.bands(&frame, *frequency_range.borrow(), &mut bands);
+                                scale_levels(&mut bands.values,
+                                             &*amplitude.borrow(),
+                                             &mut *normalizer_state.borrow_mut(),
+                                             &*normalizer.borrow(),
+                                             now);
+                                smoother.borrow_mut().apply(&mut bands.values, &*smoothing.borrow(), now);
+                                // draw it
+                                style.draw(context, &mut bands.values, &frame);
+                            } else {
+                                style.draw(context, &mut [], &frame);
+                            }
                         }
-                        // Audio Processor not ready yet
-                        None => {}
                     }
                 }
                 // move to a new position if any
@@ -115,12 +210,19 @@ impl GtkVisualizerInstance {
         // workaround for weird double popups (BUG)
         let already_spawned_popup = Rc::new(RefCell::new(false));
         {
-            let num_sources = sources.len();
-            clone_local!(index,
+            clone_local!(source,
+                         index,
+                         follow_default,
                          x_pos,
                          y_pos,
                          style,
+                         channels,
+                         frequency_range,
+                         amplitude,
+                         normalizer,
+                         smoothing,
                          already_spawned_popup,
+                         sources,
                          update_sender,
                          instance_continue);
             window.connect_button_release_event(move |window, ebutton| {
@@ -149,9 +251,9 @@ impl GtkVisualizerInstance {
                         // right click menu callbacks
                         let already_spawned_popup = already_spawned_popup.clone();
                         {
-                            clone_local!(index, x_pos, y_pos, style, update_sender, instance_continue);
+                            clone_local!(source, index, follow_default, x_pos, y_pos, style, channels, frequency_range, amplitude, normalizer, smoothing, sources, update_sender, instance_continue);
                             right_click_menu.connect_hide(move |this| {
-                                clone_local!(index, x_pos, y_pos, style, update_sender, instance_continue);
+                                clone_local!(source, index, follow_default, x_pos, y_pos, style, channels, frequency_range, amplitude, normalizer, smoothing, sources, update_sender, instance_continue);
                                 if let Some(selection) = this.get_active() {
                                     // get the index of the item
                                     match &selection.get_name().unwrap() as &str {
@@ -161,11 +263,18 @@ impl GtkVisualizerInstance {
                                         }
                                         "Edit instance settings" => {
                                             let settings = SettingsWindow::new(id,
-                                                                               num_sources,
+                                                                               sources,
+                                                                               source,
                                                                                index,
+                                                                               follow_default,
                                                                                x_pos,
                                                                                y_pos,
                                                                                style,
+                                                                               channels,
+                                                                               frequency_range,
+                                                                               amplitude,
+                                                                               normalizer,
+                                                                               smoothing,
                                                                                update_sender);
                                             settings.show_all();
                                         }
@@ -186,13 +295,20 @@ impl GtkVisualizerInstance {
 
         GtkVisualizerInstance {
             id: id,
+            source: source,
             index: index,
+            follow_default: follow_default,
             window: window,
             x_pos: x_pos,
             y_pos: y_pos,
             style: style,
+            channels: channels,
+            frequency_range: frequency_range,
+            amplitude: amplitude,
+            normalizer: normalizer,
+            smoothing: smoothing,
             msg_sender: update_sender,
-            data_sources: sources,
+            sources: sources,
             last_drawn: precise_time_ns(),
             instance_continue: instance_continue,
         }
@@ -206,6 +322,24 @@ impl GtkVisualizerInstance {
         *self.index.borrow()
     }
 
+    // reattach once the wanted source gets plugged back in
+    pub fn source_added(&self, index: usize, info: &PaSourceInfo) {
+        if *self.follow_default.borrow() || self.source.borrow().as_ref() != Some(&info.name) {
+            return;
+        }
+        let old_index = *self.index.borrow();
+        self.msg_sender.send(UpdateMessage::ChangeMapping(self.id, old_index, index)).unwrap();
+        *self.index.borrow_mut() = index;
+    }
+
+    // the updater already moved following instances over, just keep up with it
+    pub fn default_changed(&self, index: usize, info: &PaSourceInfo) {
+        if *self.follow_default.borrow() {
+            *self.source.borrow_mut() = Some(info.name.clone());
+            *self.index.borrow_mut() = index;
+        }
+    }
+
     pub fn iterate(&mut self) -> bool {
         // add a custom timer or use gtk::timout_add?
         let time_now = precise_time_ns();
@@ -1,13 +1,17 @@
 #![allow(dead_code, unused_variables, unused_imports)]
 #![feature(plugin, concat_idents)]
+#![cfg_attr(test, feature(test))]
 extern crate app_dirs;
+extern crate byteorder;
 extern crate cairo;
+extern crate claxon;
 extern crate dft;
 extern crate gtk;
 extern crate gdk;
 extern crate gdk_pixbuf;
 extern crate gdk_sys;
 extern crate glib;
+extern crate hound;
 #[macro_use]
 extern crate lazy_static;
 extern crate libc;
@@ -17,6 +21,8 @@ extern crate serde;
 #[macro_use]
 extern crate serde_derive;
 extern crate serde_yaml;
+#[cfg(test)]
+extern crate test;
 extern crate time;
 
 #[macro_use]
@@ -27,11 +33,13 @@ mod autostart;
 mod config;
 mod data_helpers;
 mod drawing;
+mod exchange;
 mod ui;
 mod instance;
 mod lockfile;
 mod message;
 mod shared_data;
+mod spectrum;
 
 use app::GtkVisualizerApp;
 
@@ -1,4 +1,6 @@
 // used by both audio_input/updater.rs and instance.rs
+use audio_input::{FrameContent, PaSourceInfo};
+use shared_data::FrameReceiver;
 
 pub enum UpdateMessage {
     // id, index
@@ -7,4 +9,18 @@ pub enum UpdateMessage {
     ChangeMapping(usize, usize, usize),
     // id, index
     Add(usize, usize),
+    // sent back from the audio updater to the app when sources come and go
+    // index, info
+    SourceAdded(usize, PaSourceInfo),
+    // index
+    SourceRemoved(usize),
+    // id, what the instance's style needs in its frames
+    Content(usize, FrameContent),
+    // id, whether it should stick to the default source
+    FollowDefault(usize, bool),
+    // sent back to the app when a worker starts on a source
+    // index, where its frames come in
+    SourceData(usize, FrameReceiver),
+    // sent back to the app, index of the new default source
+    DefaultChanged(usize),
 }
@@ -1,13 +1,93 @@
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, Ordering};
-use audio_input::AudioFrame;
+use audio_input::{FrameSummary, PaSourceInfo};
 pub use std::rc::Rc;
 pub use std::cell::RefCell;
-
-pub type SharedData = Arc<Mutex<Option<AudioFrame>>>;
+pub use exchange::{frame_exchange, FramePublisher, FrameReceiver};
 
 pub type StateHolder<T> = Rc<RefCell<T>>;
 
+// the gtk side copy of the audio updater's sources, indices line up with the updater's
+pub struct SourceTable {
+    pub info: Vec<Option<PaSourceInfo>>,
+    // swapped out for a fresh one whenever a worker starts capturing the source
+    pub data: Vec<FrameReceiver>,
+    // index of the default sink's monitor
+    pub default_index: Option<usize>,
+    // bumped whenever a source appears or disappears or the default changes, so views can refresh
+    pub generation: usize,
+}
+
+impl SourceTable {
+    pub fn new(info: Vec<Option<PaSourceInfo>>, default_index: Option<usize>) -> Self {
+        SourceTable {
+            data: info.iter().map(|_| FrameReceiver::empty()).collect(),
+            info: info,
+            default_index: default_index,
+            generation: 0,
+        }
+    }
+
+    pub fn add(&mut self, index: usize, info: PaSourceInfo) {
+        while self.info.len() <= index {
+            self.info.push(None);
+            self.data.push(FrameReceiver::empty());
+        }
+        self.info[index] = Some(info);
+        self.data[index] = FrameReceiver::empty();
+        self.generation += 1;
+    }
+
+    // a worker started capturing the source, its frames come in through data from now on
+    pub fn set_data(&mut self, index: usize, data: FrameReceiver) {
+        if index < self.data.len() {
+            self.data[index] = data;
+        }
+    }
+
+    pub fn remove(&mut self, index: usize) {
+        if index < self.info.len() {
+            self.info[index] = None;
+            self.data[index] = FrameReceiver::empty();
+            if self.default_index == Some(index) {
+                self.default_index = None;
+            }
+            self.generation += 1;
+        }
+    }
+
+    pub fn set_default(&mut self, index: usize) {
+        self.default_index = Some(index);
+        self.generation += 1;
+    }
+
+    // index of the source with this pulseaudio name
+    pub fn find(&self, name: &str) -> Option<usize> {
+        self.info.iter().position(|s| match *s {
+            Some(ref info) => info.name == name,
+            None => false,
+        })
+    }
+
+    // bpm and levels of the latest frame of a source, if it is being analysed
+    pub fn summary(&self, index: usize) -> Option<FrameSummary> {
+        self.get(index).and_then(|(data, _)| data.read_summary())
+    }
+
+    // tempo of a source, if it is being analysed and has a steady one
+    pub fn tempo(&self, index: usize) -> Option<f64> {
+        self.summary(index).and_then(|summary| summary.bpm)
+    }
+
+    // data and info of a source, if it is still around
+    pub fn get(&self, index: usize) -> Option<(&FrameReceiver, &PaSourceInfo)> {
+        match (self.data.get(index), self.info.get(index)) {
+            (Some(data), Some(&Some(ref info))) => Some((data, info)),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct ContinueState {
     inner: Arc<AtomicBool>,
@@ -0,0 +1,104 @@
+// How magnitudes (fractions of full scale) turn into drawn heights.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum AmplitudeScale {
+    Linear,
+    Sqrt,
+    Decibel,
+}
+
+impl Default for AmplitudeScale {
+    fn default() -> Self {
+        AmplitudeScale::Linear
+    }
+}
+
+// the range between floor_db and ceiling_db (dBFS) gets mapped onto 0..1 for every scale
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AmplitudeSettings {
+    pub scale: AmplitudeScale,
+    pub floor_db: f64,
+    pub ceiling_db: f64,
+}
+
+impl Default for AmplitudeSettings {
+    fn default() -> Self {
+        AmplitudeSettings {
+            scale: AmplitudeScale::Linear,
+            floor_db: -70.,
+            ceiling_db: 0.,
+        }
+    }
+}
+
+pub fn db_to_linear(db: f64) -> f64 {
+    10f64.powf(db / 20.)
+}
+
+pub fn linear_to_db(value: f64) -> f64 {
+    20. * value.log10()
+}
+
+impl AmplitudeSettings {
+    pub fn map(&self, value: f64) -> f64 {
+        // a flipped range would just draw everything upside down, keep it sane
+        let floor = f64::min(self.floor_db, self.ceiling_db);
+        let ceiling = f64::max(self.floor_db, self.ceiling_db);
+        let mapped = match self.scale {
+            AmplitudeScale::Decibel => {
+                if value <= 0. {
+                    0.
+                } else {
+                    (linear_to_db(value) - floor) / (ceiling - floor)
+                }
+            }
+            AmplitudeScale::Linear | AmplitudeScale::Sqrt => {
+                let (floor, ceiling) = (db_to_linear(floor), db_to_linear(ceiling));
+                let linear = (value - floor) / (ceiling - floor);
+                if self.scale == AmplitudeScale::Sqrt {
+                    f64::max(linear, 0.).sqrt()
+                } else {
+                    linear
+                }
+            }
+        };
+        if mapped.is_nan() {
+            0.
+        } else {
+            f64::max(0., f64::min(1., mapped))
+        }
+    }
+
+    pub fn apply(&self, bands: &mut Vec<Vec<f64>>) {
+        for channel in bands.iter_mut() {
+            for value in channel.iter_mut() {
+                *value = self.map(*value);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_amplitude_decibel() {
+    let settings = AmplitudeSettings {
+        scale: AmplitudeScale::Decibel,
+        floor_db: -60.,
+        ceiling_db: 0.,
+    };
+    assert_eq!(settings.map(0.), 0.);
+    assert_eq!(settings.map(1.), 1.);
+    assert_eq!(settings.map(2.), 1.);
+    assert!((settings.map(db_to_linear(-30.)) - 0.5).abs() < 1e-9);
+    assert_eq!(settings.map(0.000001), 0.);
+}
+
+#[test]
+fn test_amplitude_linear_and_sqrt() {
+    let mut settings = AmplitudeSettings {
+        scale: AmplitudeScale::Linear,
+        floor_db: -200.,
+        ceiling_db: linear_to_db(0.5),
+    };
+    assert!((settings.map(0.25) - 0.5).abs() < 1e-6);
+    settings.scale = AmplitudeScale::Sqrt;
+    assert!((settings.map(0.125) - 0.5).abs() < 1e-6);
+}
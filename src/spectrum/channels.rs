@@ -0,0 +1,247 @@
+use audio_input::{find_positions, AudioFrame, ChannelLayout, ChannelPosition, FrameContent};
+
+const LEFT_RIGHT: [ChannelPosition; 2] = [ChannelPosition::FrontLeft, ChannelPosition::FrontRight];
+
+// speakers of 5.1 and 7.1 setups, found by position since every kind of source orders them differently
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum SurroundGroup {
+    Front,
+    Center,
+    Lfe,
+    Rear,
+    Side,
+}
+
+impl SurroundGroup {
+    pub fn positions(&self) -> &'static [ChannelPosition] {
+        match *self {
+            SurroundGroup::Front => &LEFT_RIGHT,
+            SurroundGroup::Center => &[ChannelPosition::FrontCenter],
+            SurroundGroup::Lfe => &[ChannelPosition::Lfe],
+            SurroundGroup::Rear => &[ChannelPosition::RearLeft, ChannelPosition::RearRight],
+            SurroundGroup::Side => &[ChannelPosition::SideLeft, ChannelPosition::SideRight],
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            SurroundGroup::Front => "Front",
+            SurroundGroup::Center => "Center",
+            SurroundGroup::Lfe => "LFE",
+            SurroundGroup::Rear => "Rear",
+            SurroundGroup::Side => "Side",
+        }
+    }
+}
+
+pub const SURROUND_GROUPS: [SurroundGroup; 5] = [SurroundGroup::Front,
+                                                  SurroundGroup::Center,
+                                                  SurroundGroup::Lfe,
+                                                  SurroundGroup::Rear,
+                                                  SurroundGroup::Side];
+
+// which channels of its source an instance draws, applied before the style sees the frame
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum ChannelMapping {
+    // every channel, front left and right first
+    All,
+    // everything averaged into one channel
+    Mono,
+    LeftRight,
+    // sum and difference of left and right
+    MidSide,
+    // one channel, counting from 0
+    Single(usize),
+    Surround(SurroundGroup),
+}
+
+impl Default for ChannelMapping {
+    fn default() -> Self {
+        ChannelMapping::All
+    }
+}
+
+impl ChannelMapping {
+    // whether a source with channels in these positions can be mapped like this
+    pub fn validate(&self, positions: &[ChannelPosition]) -> Result<(), String> {
+        let fits = match *self {
+            ChannelMapping::All | ChannelMapping::Mono => !positions.is_empty(),
+            ChannelMapping::LeftRight | ChannelMapping::MidSide => find_positions(positions, &LEFT_RIGHT).is_some(),
+            ChannelMapping::Single(channel) => channel < positions.len(),
+            ChannelMapping::Surround(group) => find_positions(positions, group.positions()).is_some(),
+        };
+        if fits {
+            Ok(())
+        } else {
+            Err(format!("Source has no channels for {}", self.name()))
+        }
+    }
+
+    // mid/side can't be worked out from magnitudes, the processor has to transform it
+    pub fn content(&self) -> FrameContent {
+        FrameContent {
+            spectrum: false,
+            waveform: false,
+            mid_side: *self == ChannelMapping::MidSide,
+        }
+    }
+
+    pub fn name(&self) -> String {
+        match *self {
+            ChannelMapping::All => "All Channels".to_string(),
+            ChannelMapping::Mono => "Mono Downmix".to_string(),
+            ChannelMapping::LeftRight => "Left/Right".to_string(),
+            ChannelMapping::MidSide => "Mid/Side".to_string(),
+            ChannelMapping::Single(channel) => format!("Channel {}", channel + 1),
+            ChannelMapping::Surround(group) => format!("Surround {}", group.name()),
+        }
+    }
+
+    // every mapping a source with channels in these positions can use
+    pub fn choices(positions: &[ChannelPosition]) -> Vec<ChannelMapping> {
+        let mut choices = vec![ChannelMapping::All,
+                               ChannelMapping::Mono,
+                               ChannelMapping::LeftRight,
+                               ChannelMapping::MidSide];
+        choices.extend((0..positions.len()).map(ChannelMapping::Single));
+        choices.extend(SURROUND_GROUPS.iter().map(|&g| ChannelMapping::Surround(g)));
+        choices.retain(|m| m.validate(positions).is_ok());
+        choices
+    }
+
+    // rewrite the frame's magnitudes (and samples) to the mapped channels,
+    // positions are where the source's channels go
+    pub fn apply(&self, frame: &mut AudioFrame, positions: &[ChannelPosition]) -> Result<(), String> {
+        if frame.channels() != positions.len() {
+            return Err(format!("Expected {} channels, got {}", positions.len(), frame.channels()));
+        }
+        try!(self.validate(positions));
+        match *self {
+            ChannelMapping::All => {
+                // styles take the first two channels as left and right
+                if positions.windows(2).all(|p| p[0].code() <= p[1].code()) {
+                    return Ok(());
+                }
+                let mut order: Vec<usize> = (0..positions.len()).collect();
+                order.sort_by_key(|&c| positions[c].code());
+                pick(frame, &order);
+            }
+            ChannelMapping::Mono => {
+                downmix(&mut frame.magnitudes);
+                if let Some(ref mut samples) = frame.samples {
+                    downmix(samples);
+                }
+            }
+            ChannelMapping::MidSide => {
+                match frame.mid_side.take() {
+                    Some(mid_side) => frame.magnitudes = mid_side,
+                    // the processor hasn't picked up the request yet
+                    None => return Err("Waiting for mid/side data".to_string()),
+                }
+                if let Some(ref mut samples) = frame.samples {
+                    // the same channels the processor transformed
+                    let (left, right) = match find_positions(positions, &LEFT_RIGHT) {
+                        Some(found) => (found[0], found[1]),
+                        None => (0, 0),
+                    };
+                    let (mid, side) = samples[left]
+                        .iter()
+                        .zip(samples[right].iter())
+                        .map(|(l, r)| ((l + r) / 2., (l - r) / 2.))
+                        .unzip();
+                    *samples = vec![mid, side];
+                }
+            }
+            ChannelMapping::LeftRight => pick(frame, &find_positions(positions, &LEFT_RIGHT).unwrap()),
+            ChannelMapping::Single(channel) => pick(frame, &[channel]),
+            ChannelMapping::Surround(group) => pick(frame, &find_positions(positions, group.positions()).unwrap()),
+        }
+        frame.layout = ChannelLayout::from_channels(frame.magnitudes.len());
+        Ok(())
+    }
+}
+
+fn downmix(data: &mut Vec<Vec<f64>>) {
+    let count = data.len() as f64;
+    let mut mixed = data.swap_remove(0);
+    for channel in data.iter() {
+        for (m, v) in mixed.iter_mut().zip(channel.iter()) {
+            *m += *v;
+        }
+    }
+    for m in mixed.iter_mut() {
+        *m /= count;
+    }
+    *data = vec![mixed];
+}
+
+fn pick(frame: &mut AudioFrame, channels: &[usize]) {
+    let select = |data: &mut Vec<Vec<f64>>| {
+        let mut picked: Vec<Vec<f64>> = channels.iter().map(|_| Vec::new()).collect();
+        for (out, &c) in picked.iter_mut().zip(channels.iter()) {
+            ::std::mem::swap(out, &mut data[c]);
+        }
+        *data = picked;
+    };
+    select(&mut frame.magnitudes);
+    if let Some(ref mut samples) = frame.samples {
+        select(samples);
+    }
+}
+
+#[test]
+fn test_channel_mapping() {
+    let frame = AudioFrame {
+        magnitudes: vec![vec![1.], vec![3.], vec![5.], vec![7.], vec![9.], vec![11.]],
+        ..AudioFrame::default()
+    };
+    let positions = ChannelPosition::file_order(6);
+    let mut mono = frame.clone();
+    ChannelMapping::Mono.apply(&mut mono, &positions).unwrap();
+    assert_eq!(mono.magnitudes, vec![vec![6.]]);
+    assert_eq!(mono.layout, ChannelLayout::Mono);
+    let mut rear = frame.clone();
+    ChannelMapping::Surround(SurroundGroup::Rear).apply(&mut rear, &positions).unwrap();
+    assert_eq!(rear.magnitudes, vec![vec![9.], vec![11.]]);
+    assert!(ChannelMapping::Surround(SurroundGroup::Side).apply(&mut frame.clone(), &positions).is_err());
+    // a mono source can't be split
+    let mono_positions = ChannelPosition::pulse_order(1);
+    let mut single = AudioFrame { magnitudes: vec![vec![1.]], ..AudioFrame::default() };
+    assert!(ChannelMapping::LeftRight.apply(&mut single, &mono_positions).is_err());
+    assert_eq!(ChannelMapping::choices(&mono_positions),
+               vec![ChannelMapping::All, ChannelMapping::Mono, ChannelMapping::Single(0)]);
+}
+
+#[test]
+fn test_channel_mapping_pulse_order() {
+    // front left, rear left, center, front right, rear right, lfe
+    let frame = AudioFrame {
+        magnitudes: vec![vec![1.], vec![3.], vec![5.], vec![7.], vec![9.], vec![11.]],
+        ..AudioFrame::default()
+    };
+    let positions = ChannelPosition::pulse_order(6);
+    let mut left_right = frame.clone();
+    ChannelMapping::LeftRight.apply(&mut left_right, &positions).unwrap();
+    assert_eq!(left_right.magnitudes, vec![vec![1.], vec![7.]]);
+    let mut lfe = frame.clone();
+    ChannelMapping::Surround(SurroundGroup::Lfe).apply(&mut lfe, &positions).unwrap();
+    assert_eq!(lfe.magnitudes, vec![vec![11.]]);
+    let mut all = frame.clone();
+    ChannelMapping::All.apply(&mut all, &positions).unwrap();
+    assert_eq!(all.stereo(), (&[1.][..], &[7.][..]));
+    assert_eq!(all.magnitudes.len(), 6);
+}
+
+#[test]
+fn test_mid_side_pulse_order() {
+    // front right is the fourth channel, not the second
+    let mut frame = AudioFrame {
+        magnitudes: vec![vec![0.]; 6],
+        samples: Some(vec![vec![1.], vec![3.], vec![5.], vec![7.], vec![9.], vec![11.]]),
+        mid_side: Some(vec![vec![2.], vec![4.]]),
+        ..AudioFrame::default()
+    };
+    ChannelMapping::MidSide.apply(&mut frame, &ChannelPosition::pulse_order(6)).unwrap();
+    assert_eq!(frame.magnitudes, vec![vec![2.], vec![4.]]);
+    assert_eq!(frame.samples, Some(vec![vec![4.], vec![-3.]]));
+}
@@ -0,0 +1,291 @@
+use data_helpers::resize_to;
+
+// range of hearing, anything outside of it isn't worth a band
+const LOWEST_FREQUENCY: f64 = 20.;
+const HIGHEST_FREQUENCY: f64 = 20000.;
+// band centers are counted from here, like the standard third octave bands
+const REFERENCE_FREQUENCY: f64 = 1000.;
+
+// how fft bins get grouped into the values that are drawn
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum FrequencyScale {
+    // evenly sized groups of bins
+    Linear,
+    // fractional octave bands, the number is how many bands make up an octave (3 or 6 are usual)
+    Octave(u32),
+    // triangular filterbanks spaced evenly on a perceptual pitch scale
+    Mel,
+    Bark,
+}
+
+impl Default for FrequencyScale {
+    fn default() -> Self {
+        FrequencyScale::Linear
+    }
+}
+
+// the part of the spectrum an instance shows. an end that isn't set stays where the scale
+// puts it: the linear scale keeps every bin, the others go from 20 Hz to 20 kHz
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct FrequencyRange {
+    #[serde(default)]
+    pub min_hz: Option<f64>,
+    #[serde(default)]
+    pub max_hz: Option<f64>,
+}
+
+impl FrequencyRange {
+    // lowest and highest frequency a source with this rate has within the range,
+    // with default_low and default_high for the ends that aren't set
+    fn limits(&self, rate: u32, default_low: f64, default_high: f64) -> (f64, f64) {
+        let high = self.max_hz.unwrap_or(default_high).min(rate as f64 / 2.).max(0.);
+        let low = self.min_hz.unwrap_or(default_low).max(0.).min(high);
+        (low, high)
+    }
+
+    fn is_uncropped(&self) -> bool {
+        self.min_hz.is_none() && self.max_hz.is_none()
+    }
+}
+
+// map the magnitudes of one channel within range into n values, replacing what was in out
+pub fn map_frequencies(items: &[f64],
+                       rate: u32,
+                       scale: FrequencyScale,
+                       range: FrequencyRange,
+                       n: usize,
+                       out: &mut Vec<f64>) {
+    out.clear();
+    match scale {
+        FrequencyScale::Linear => linear_bins(items, rate, range, out),
+        FrequencyScale::Octave(bands_per_octave) => {
+            octave_bands(items, rate, range, bands_per_octave, out)
+        }
+        FrequencyScale::Mel => filterbank(items, rate, range, n, hz_to_mel, mel_to_hz, out),
+        FrequencyScale::Bark => filterbank(items, rate, range, n, hz_to_bark, bark_to_hz, out),
+    }
+    resize_to(out, n);
+}
+
+// the bins within range as they are, all of them (mirrored half too) when there is no range
+fn linear_bins(items: &[f64], rate: u32, range: FrequencyRange, out: &mut Vec<f64>) {
+    if range.is_uncropped() {
+        out.extend_from_slice(items);
+        return;
+    }
+    let bin_width = rate as f64 / items.len() as f64;
+    let (low, high) = range.limits(rate, 0., rate as f64 / 2.);
+    let usable = items.len() / 2 + 1;
+    let start = (low / bin_width).ceil() as usize;
+    let end = ::std::cmp::min((high / bin_width).floor() as usize + 1, usable);
+    if start < end {
+        out.extend_from_slice(&items[start..end]);
+    } else {
+        // narrower than a bin
+        out.push(interpolate_at(items, bin_width, (low + high) / 2.));
+    }
+}
+
+// items is the whole unpacked spectrum, only the half up to the nyquist frequency is used
+fn octave_bands(items: &[f64],
+                rate: u32,
+                range: FrequencyRange,
+                bands_per_octave: u32,
+                bands: &mut Vec<f64>) {
+    let bands_per_octave = ::std::cmp::max(bands_per_octave, 1) as f64;
+    let bin_width = rate as f64 / items.len() as f64;
+    let (lowest, highest) = range.limits(rate, LOWEST_FREQUENCY, HIGHEST_FREQUENCY);
+    // no band reaches down to 0 Hz on a log scale
+    let lowest = lowest.max(1.);
+    // edges sit half a band away from the centers
+    let half_band = 2f64.powf(0.5 / bands_per_octave);
+
+    let first = ((lowest / REFERENCE_FREQUENCY).log2() * bands_per_octave).ceil() as i32;
+    let last = ((highest / REFERENCE_FREQUENCY).log2() * bands_per_octave).floor() as i32;
+    for k in first..last + 1 {
+        let center = REFERENCE_FREQUENCY * 2f64.powf(k as f64 / bands_per_octave);
+        // the outer bands get cut off at the edges of the range
+        let low = (center / half_band).max(lowest);
+        let high = (center * half_band).min(highest);
+        bands.push(band_average(items, bin_width, low, high, center));
+    }
+    if bands.is_empty() {
+        // sample rate too low for any band in the range, fall back to something drawable
+        bands.push(items[0]);
+    }
+}
+
+// n triangular filters, evenly spaced between the lowest and highest frequency on the scale
+// given by to_scale/from_scale, each one overlapping halfway into its neighbours
+fn filterbank<F, G>(items: &[f64],
+                   rate: u32,
+                   range: FrequencyRange,
+                   n: usize,
+                   to_scale: F,
+                   from_scale: G,
+                   out: &mut Vec<f64>)
+    where F: Fn(f64) -> f64,
+          G: Fn(f64) -> f64
+{
+    let bin_width = rate as f64 / items.len() as f64;
+    let (lowest, highest) = range.limits(rate, LOWEST_FREQUENCY, HIGHEST_FREQUENCY);
+    let lowest = to_scale(lowest);
+    let highest = to_scale(highest);
+    let step = (highest - lowest) / (n + 1) as f64;
+    out.extend((0..n).map(|k| {
+        let left = from_scale(lowest + k as f64 * step);
+        let center = from_scale(lowest + (k + 1) as f64 * step);
+        let right = from_scale(lowest + (k + 2) as f64 * step);
+        triangle_average(items, bin_width, left, center, right)
+    }));
+}
+
+pub fn hz_to_mel(hz: f64) -> f64 {
+    2595. * (1. + hz / 700.).log10()
+}
+
+pub fn mel_to_hz(mel: f64) -> f64 {
+    700. * (10f64.powf(mel / 2595.) - 1.)
+}
+
+// Traunmüller's approximation
+pub fn hz_to_bark(hz: f64) -> f64 {
+    26.81 * hz / (1960. + hz) - 0.53
+}
+
+pub fn bark_to_hz(bark: f64) -> f64 {
+    1960. * (bark + 0.53) / (26.28 - bark)
+}
+
+// average of the bins within [low, high), or the value interpolated at the center
+// when the band is narrower than a bin
+fn band_average(items: &[f64], bin_width: f64, low: f64, high: f64, center: f64) -> f64 {
+    let usable = items.len() / 2 + 1;
+    let start = (low / bin_width).ceil() as usize;
+    let end = ::std::cmp::min((high / bin_width).ceil() as usize, usable);
+    if start < end {
+        items[start..end].iter().fold(0., |acc, &x| acc + x) / (end - start) as f64
+    } else {
+        interpolate_at(items, bin_width, center)
+    }
+}
+
+// weighted average of the bins under a triangle peaking at center,
+// falls back to interpolating like band_average
+fn triangle_average(items: &[f64], bin_width: f64, left: f64, center: f64, right: f64) -> f64 {
+    let usable = items.len() / 2 + 1;
+    let start = (left / bin_width).ceil() as usize;
+    let end = ::std::cmp::min((right / bin_width).ceil() as usize, usable);
+    let mut total = 0.;
+    let mut total_weight = 0.;
+    for i in start..end {
+        let frequency = i as f64 * bin_width;
+        let weight = if frequency < center {
+            (frequency - left) / (center - left)
+        } else {
+            (right - frequency) / (right - center)
+        };
+        total += items[i] * weight;
+        total_weight += weight;
+    }
+    if total_weight > 0. {
+        total / total_weight
+    } else {
+        interpolate_at(items, bin_width, center)
+    }
+}
+
+fn interpolate_at(items: &[f64], bin_width: f64, frequency: f64) -> f64 {
+    let usable = items.len() / 2 + 1;
+    let position = frequency / bin_width;
+    let lower = ::std::cmp::min(position.floor() as usize, usable - 1);
+    let upper = ::std::cmp::min(lower + 1, usable - 1);
+    let fraction = position - lower as f64;
+    items[lower] * (1. - fraction) + items[upper] * fraction
+}
+
+#[test]
+fn test_map_frequencies_linear() {
+    let data = vec![0., 1., 2., 3., 4., 5., 6., 7., 8.];
+    let mut bands = Vec::new();
+    map_frequencies(&data, 44100, FrequencyScale::Linear, FrequencyRange::default(), 3, &mut bands);
+    assert_eq!(bands.as_slice(), &[1., 4., 7.]);
+}
+
+#[test]
+fn test_map_frequencies_linear_range() {
+    // 1 Hz bins, only the half up to the nyquist frequency has anything
+    let data: Vec<f64> = (0..16).map(|i| i as f64).collect();
+    let range = FrequencyRange {
+        min_hz: Some(0.),
+        max_hz: Some(20000.),
+    };
+    let mut bands = Vec::new();
+    map_frequencies(&data, 16, FrequencyScale::Linear, range, 3, &mut bands);
+    assert_eq!(bands.as_slice(), &[1., 4., 7.]);
+}
+
+#[test]
+fn test_map_frequencies_cropped() {
+    let data: Vec<f64> = (0..1024).map(|i| i as f64).collect();
+    let range = FrequencyRange {
+        min_hz: Some(100.),
+        max_hz: Some(200.),
+    };
+    // 1024 Hz makes every bin 1 Hz wide
+    let mut bands = Vec::new();
+    map_frequencies(&data, 1024, FrequencyScale::Linear, range, 101, &mut bands);
+    assert_eq!(bands.first(), Some(&100.));
+    assert_eq!(bands.last(), Some(&200.));
+    for &scale in [FrequencyScale::Octave(3), FrequencyScale::Mel, FrequencyScale::Bark].iter() {
+        // the same buffer gets refilled
+        map_frequencies(&data, 1024, scale, range, 4, &mut bands);
+        assert_eq!(bands.len(), 4);
+        assert!(bands.iter().all(|&b| b >= 99. && b <= 201.), "{:?} {:?}", scale, bands);
+    }
+}
+
+#[test]
+fn test_octave_bands() {
+    use data_helpers::peak_index;
+    // 8192 points at 8192 Hz makes every bin exactly 1 Hz wide
+    let mut data = vec![0.; 8192];
+    data[1000] = 1.;
+    let mut bands = Vec::new();
+    octave_bands(&data, 8192, FrequencyRange::default(), 3, &mut bands);
+    // 20 Hz up to 4096 Hz: bands -16 through 6 around 1 kHz
+    assert_eq!(bands.len(), 23);
+    assert_eq!(peak_index(&bands), 16);
+}
+
+#[test]
+fn test_perceptual_scales_round_trip() {
+    for &hz in [20., 440., 1000., 12345.].iter() {
+        assert!((mel_to_hz(hz_to_mel(hz)) - hz).abs() < 1e-6);
+        assert!((bark_to_hz(hz_to_bark(hz)) - hz).abs() < 1e-6);
+    }
+}
+
+#[test]
+fn test_filterbank_flat_spectrum() {
+    let data = vec![2.; 8192];
+    for &scale in [FrequencyScale::Mel, FrequencyScale::Bark].iter() {
+        let mut bands = Vec::new();
+        map_frequencies(&data, 44100, scale, FrequencyRange::default(), 40, &mut bands);
+        assert_eq!(bands.len(), 40);
+        assert!(bands.iter().all(|&b| (b - 2.).abs() < 1e-9));
+    }
+}
+
+#[test]
+fn test_filterbank_tone() {
+    use data_helpers::peak_index;
+    let mut data = vec![0.; 8192];
+    data[1000] = 1.;
+    let mut bands = Vec::new();
+    map_frequencies(&data, 8192, FrequencyScale::Mel, FrequencyRange::default(), 30, &mut bands);
+    // the band centered closest to 1 kHz
+    let step = (hz_to_mel(4096.) - hz_to_mel(20.)) / 31.;
+    let expected = ((hz_to_mel(1000.) - hz_to_mel(20.)) / step).round() as usize - 1;
+    assert_eq!(peak_index(&bands), expected);
+}
@@ -0,0 +1,58 @@
+// Turning the raw magnitudes from the audio processors into what the drawing styles show.
+mod amplitude;
+mod channels;
+mod frequency;
+mod normalizer;
+mod smoothing;
+
+pub use self::amplitude::{AmplitudeScale, AmplitudeSettings};
+pub use self::channels::{ChannelMapping, SurroundGroup, SURROUND_GROUPS};
+pub use self::frequency::{FrequencyRange, FrequencyScale, map_frequencies};
+pub use self::normalizer::{Normalizer, NormalizerMode, NormalizerSettings};
+pub use self::smoothing::{Smoother, SmoothingSettings};
+
+// raw magnitudes from the audio processors are sums over the whole transform,
+// bring them to fractions of what a full scale sine wave would reach
+pub fn to_full_scale(data: &mut Vec<Vec<f64>>) {
+    for channel in data.iter_mut() {
+        let full_scale = (::std::i16::MAX as f64 + 1.) * channel.len() as f64 / 2.;
+        for value in channel.iter_mut() {
+            *value /= full_scale;
+        }
+    }
+}
+
+// auto gain works on linear levels, the amplitude curve comes after it so the configured
+// floor and ceiling still land on the bottom and top of the drawing area
+pub fn scale_levels(bands: &mut Vec<Vec<f64>>,
+                    amplitude: &AmplitudeSettings,
+                    normalizer: &mut Normalizer,
+                    normalizer_settings: &NormalizerSettings,
+                    time: u64) {
+    normalizer.apply(bands, normalizer_settings, time);
+    amplitude.apply(bands);
+}
+
+#[test]
+fn test_to_full_scale() {
+    let mut data = vec![vec![0., 32768. * 2., 0., 32768.]];
+    to_full_scale(&mut data);
+    assert_eq!(data, vec![vec![0., 1., 0., 0.5]]);
+}
+
+#[test]
+fn test_scale_levels_keeps_ceiling() {
+    use self::amplitude::linear_to_db;
+    // auto gain on by default, pulling the loudest band up to 0.9
+    let normalizer_settings = NormalizerSettings::default();
+    let amplitude = AmplitudeSettings {
+        scale: AmplitudeScale::Decibel,
+        floor_db: -60.,
+        ceiling_db: linear_to_db(normalizer_settings.target),
+    };
+    let mut bands = vec![vec![0.01, 0.0009, 0.]];
+    scale_levels(&mut bands, &amplitude, &mut Normalizer::new(), &normalizer_settings, 0);
+    assert_eq!(bands[0][0], 1.);
+    assert!((bands[0][1] - amplitude.map(0.081)).abs() < 1e-9);
+    assert_eq!(bands[0][2], 0.);
+}
@@ -0,0 +1,168 @@
+use std::collections::VecDeque;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum NormalizerMode {
+    Off,
+    // one gain for everything, keeps the shape of the spectrum
+    Global,
+    // every band gets its own gain, flattens the spectrum out
+    PerBand,
+}
+
+// Automatic gain so quiet and loud audio both fill up the drawing area.
+// Values are expected as fractions of full scale.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct NormalizerSettings {
+    pub mode: NormalizerMode,
+    // where the tracked level ends up, 1 is the full height
+    pub target: f64,
+    // how far back the tracked level looks, in ms
+    pub response_ms: f64,
+    // which percentile of the recent values gets tracked, 100 tracks the peak
+    pub percentile: f64,
+    // quietest level that still gets boosted all the way up to the target
+    pub floor: f64,
+}
+
+impl Default for NormalizerSettings {
+    fn default() -> Self {
+        NormalizerSettings {
+            mode: NormalizerMode::Global,
+            target: 0.9,
+            response_ms: 3000.,
+            percentile: 95.,
+            floor: 0.0005,
+        }
+    }
+}
+
+// recent levels of one instance
+pub struct Normalizer {
+    // time in ns, then one level for Global or one per band for PerBand
+    history: VecDeque<(u64, Vec<f64>)>,
+    // buffers of levels that left the history, reused so drawing doesn't allocate
+    spare: Vec<Vec<f64>>,
+    recent: Vec<f64>,
+    gains: Vec<f64>,
+}
+
+impl Normalizer {
+    pub fn new() -> Self {
+        Normalizer {
+            history: VecDeque::new(),
+            spare: Vec::new(),
+            recent: Vec::new(),
+            gains: Vec::new(),
+        }
+    }
+
+    // scale the bands in place, time is the current time in ns
+    pub fn apply(&mut self, bands: &mut Vec<Vec<f64>>, settings: &NormalizerSettings, time: u64) {
+        let mut levels = self.spare.pop().unwrap_or_else(Vec::new);
+        levels.clear();
+        match settings.mode {
+            NormalizerMode::Off => {
+                self.spare.push(levels);
+                self.spare.extend(self.history.drain(..).map(|(_, l)| l));
+                return;
+            }
+            NormalizerMode::Global => {
+                levels.push(bands.iter()
+                    .flat_map(|channel| channel.iter())
+                    .cloned()
+                    .fold(0., f64::max));
+            }
+            NormalizerMode::PerBand => {
+                levels.extend(bands.iter().flat_map(|channel| channel.iter()).cloned());
+            }
+        }
+
+        // start over whenever the mode or number of bands changes
+        if self.history.back().map_or(false, |&(_, ref last)| last.len() != levels.len()) {
+            self.spare.extend(self.history.drain(..).map(|(_, l)| l));
+        }
+        let window = (settings.response_ms.max(0.) * 1e6) as u64;
+        while self.history.front().map_or(false, |&(t, _)| t + window < time) {
+            let (_, old) = self.history.pop_front().unwrap();
+            self.spare.push(old);
+        }
+        self.history.push_back((time, levels));
+
+        self.gains.clear();
+        for i in 0..self.history[0].1.len() {
+            self.recent.clear();
+            self.recent.extend(self.history.iter().map(|&(_, ref l)| l[i]));
+            let reference = percentile(&mut self.recent, settings.percentile);
+            self.gains.push(settings.target / reference.max(settings.floor));
+        }
+
+        let mut i = 0;
+        for channel in bands.iter_mut() {
+            for value in channel.iter_mut() {
+                let gain = if self.gains.len() == 1 { self.gains[0] } else { self.gains[i] };
+                *value = (*value * gain).max(0.).min(1.);
+                i += 1;
+            }
+        }
+    }
+}
+
+// the value below which p percent of the items fall
+fn percentile(items: &mut [f64], p: f64) -> f64 {
+    // unstable sorting doesn't need a buffer
+    items.sort_unstable_by(|a, b| a.partial_cmp(b).unwrap_or(::std::cmp::Ordering::Equal));
+    let p = p.max(0.).min(100.);
+    let idx = (p / 100. * (items.len() - 1) as f64).round() as usize;
+    items[idx]
+}
+
+#[test]
+fn test_percentile() {
+    let mut data = vec![5., 1., 4., 2., 3.];
+    assert_eq!(percentile(&mut data, 100.), 5.);
+    assert_eq!(percentile(&mut data, 50.), 3.);
+    assert_eq!(percentile(&mut data, 0.), 1.);
+}
+
+#[test]
+fn test_normalizer_global() {
+    let settings = NormalizerSettings {
+        mode: NormalizerMode::Global,
+        target: 1.,
+        response_ms: 1000.,
+        percentile: 100.,
+        floor: 0.001,
+    };
+    let mut normalizer = Normalizer::new();
+    let mut bands = vec![vec![0.01, 0.02], vec![0.04]];
+    normalizer.apply(&mut bands, &settings, 0);
+    assert_eq!(bands, vec![vec![0.25, 0.5], vec![1.]]);
+
+    // a louder frame only gets pulled down, the earlier quiet peak is within the window
+    let mut bands = vec![vec![0.08, 0.], vec![0.]];
+    normalizer.apply(&mut bands, &settings, 500_000_000);
+    assert_eq!(bands, vec![vec![1., 0.], vec![0.]]);
+    let mut bands = vec![vec![0.04, 0.], vec![0.]];
+    normalizer.apply(&mut bands, &settings, 1_000_000_000);
+    assert_eq!(bands, vec![vec![0.5, 0.], vec![0.]]);
+    // once the loud frame has left the window the quiet one fills the area again
+    let mut bands = vec![vec![0.04, 0.], vec![0.]];
+    normalizer.apply(&mut bands, &settings, 2_000_000_000);
+    assert_eq!(bands, vec![vec![1., 0.], vec![0.]]);
+}
+
+#[test]
+fn test_normalizer_per_band_floor() {
+    let settings = NormalizerSettings {
+        mode: NormalizerMode::PerBand,
+        target: 0.5,
+        response_ms: 1000.,
+        percentile: 100.,
+        floor: 0.01,
+    };
+    let mut normalizer = Normalizer::new();
+    let mut bands = vec![vec![0.2, 0.001]];
+    normalizer.apply(&mut bands, &settings, 0);
+    // silence below the floor isn't blown up to the target
+    assert_eq!(bands, vec![vec![0.5, 0.05]]);
+}
@@ -0,0 +1,161 @@
+// How quickly the drawn values follow the audio. Times are in milliseconds,
+// 0 means following instantly.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SmoothingSettings {
+    pub attack_ms: f64,
+    pub release_ms: f64,
+    // cava style falloff: when above 0 a falling band drops away from its last peak with
+    // this acceleration (fraction of the peak per second squared) instead of releasing
+    pub gravity: f64,
+}
+
+impl Default for SmoothingSettings {
+    fn default() -> Self {
+        SmoothingSettings {
+            attack_ms: 10.,
+            release_ms: 120.,
+            gravity: 0.,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct BandState {
+    value: f64,
+    peak: f64,
+    // seconds since the band started falling from its peak
+    falling_for: f64,
+}
+
+// keeps the previously drawn values of one instance around between frames
+pub struct Smoother {
+    bands: Vec<Vec<BandState>>,
+    last_update: Option<u64>,
+}
+
+impl Smoother {
+    pub fn new() -> Self {
+        Smoother {
+            bands: Vec::new(),
+            last_update: None,
+        }
+    }
+
+    // smooth the bands in place, time is the current time in ns
+    pub fn apply(&mut self, bands: &mut Vec<Vec<f64>>, settings: &SmoothingSettings, time: u64) {
+        let same_shape = self.bands.len() == bands.len() &&
+                         self.bands.iter().zip(bands.iter()).all(|(a, b)| a.len() == b.len());
+        let elapsed = match self.last_update {
+            Some(last) if same_shape && time > last => (time - last) as f64 / 1e9,
+            // start over whenever the number of bands changes
+            _ => {
+                self.bands = bands.iter()
+                    .map(|channel| {
+                        channel.iter()
+                            .map(|&v| {
+                                BandState {
+                                    value: v,
+                                    peak: v,
+                                    falling_for: 0.,
+                                }
+                            })
+                            .collect()
+                    })
+                    .collect();
+                self.last_update = Some(time);
+                return;
+            }
+        };
+        self.last_update = Some(time);
+
+        let attack = follow_coefficient(settings.attack_ms, elapsed);
+        let release = follow_coefficient(settings.release_ms, elapsed);
+        for (states, channel) in self.bands.iter_mut().zip(bands.iter_mut()) {
+            for (state, value) in states.iter_mut().zip(channel.iter_mut()) {
+                if *value >= state.value {
+                    state.value += (*value - state.value) * attack;
+                    state.peak = state.value;
+                    state.falling_for = 0.;
+                } else if settings.gravity > 0. {
+                    state.falling_for += elapsed;
+                    let fallen = state.peak *
+                                 (1. - settings.gravity * state.falling_for.powi(2));
+                    state.value = value.max(fallen);
+                } else {
+                    state.value += (*value - state.value) * release;
+                }
+                *value = state.value;
+            }
+        }
+    }
+}
+
+// fraction of the remaining distance to cover after elapsed seconds
+// for a one pole filter with the given time constant
+fn follow_coefficient(time_ms: f64, elapsed: f64) -> f64 {
+    if time_ms <= 0. {
+        1.
+    } else {
+        1. - (-elapsed * 1000. / time_ms).exp()
+    }
+}
+
+#[test]
+fn test_smoothing_instant() {
+    let settings = SmoothingSettings {
+        attack_ms: 0.,
+        release_ms: 0.,
+        gravity: 0.,
+    };
+    let mut smoother = Smoother::new();
+    let mut bands = vec![vec![0., 1.]];
+    smoother.apply(&mut bands, &settings, 0);
+    let mut bands = vec![vec![1., 0.]];
+    smoother.apply(&mut bands, &settings, 10_000_000);
+    assert_eq!(bands, vec![vec![1., 0.]]);
+}
+
+#[test]
+fn test_smoothing_release() {
+    let settings = SmoothingSettings {
+        attack_ms: 0.,
+        release_ms: 100.,
+        gravity: 0.,
+    };
+    let mut smoother = Smoother::new();
+    let mut bands = vec![vec![1.]];
+    smoother.apply(&mut bands, &settings, 0);
+    // one time constant later the value has fallen by 1 - 1/e
+    let mut bands = vec![vec![0.]];
+    smoother.apply(&mut bands, &settings, 100_000_000);
+    assert!((bands[0][0] - (-1f64).exp()).abs() < 1e-9);
+}
+
+#[test]
+fn test_smoothing_gravity() {
+    let settings = SmoothingSettings {
+        attack_ms: 0.,
+        release_ms: 0.,
+        gravity: 1.,
+    };
+    let mut smoother = Smoother::new();
+    let mut bands = vec![vec![1.]];
+    smoother.apply(&mut bands, &settings, 0);
+    let mut bands = vec![vec![0.]];
+    smoother.apply(&mut bands, &settings, 500_000_000);
+    assert!((bands[0][0] - 0.75).abs() < 1e-9);
+    let mut bands = vec![vec![0.]];
+    smoother.apply(&mut bands, &settings, 1_000_000_000);
+    assert_eq!(bands[0][0], 0.);
+}
+
+#[test]
+fn test_smoothing_reset_on_shape_change() {
+    let settings = SmoothingSettings::default();
+    let mut smoother = Smoother::new();
+    let mut bands = vec![vec![1., 1.]];
+    smoother.apply(&mut bands, &settings, 0);
+    let mut bands = vec![vec![0.5, 0.5, 0.5]];
+    smoother.apply(&mut bands, &settings, 1_000_000);
+    assert_eq!(bands, vec![vec![0.5, 0.5, 0.5]]);
+}
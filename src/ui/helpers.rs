@@ -9,6 +9,13 @@ pub fn is_right_click(ebutton: &EventButton) -> bool {
     }
 }
 
+pub fn tempo_text(bpm: Option<f64>) -> String {
+    match bpm {
+        Some(bpm) => format!("{:.1} BPM", bpm),
+        None => "No steady beat".to_string(),
+    }
+}
+
 #[macro_export]
 macro_rules! make_bool_changer {
     ($name:expr, $fieldname:ident, $this_struct:ident, $unwrapper_fn:ident, $unwrapper_fn_mut:ident) => {
@@ -1,12 +1,13 @@
 use std::collections::HashMap;
 use std::sync::mpsc::Sender;
 use instance::GtkVisualizerInstance;
-use shared_data::{ContinueState, StateHolder, SharedData};
+use shared_data::{ContinueState, SourceTable, StateHolder};
 use gtk::{StatusIcon, Menu, MenuItem};
 use gdk_pixbuf::PixbufLoader;
-use super::helpers::is_right_click;
+use super::helpers::{is_right_click, tempo_text};
 use message::UpdateMessage;
 use config::GtkVisualizerConfig;
+use gtk;
 use gtk::prelude::*;
 
 pub fn default_status_icon() -> Result<StatusIcon, String> {
@@ -29,11 +30,30 @@ pub fn default_status_icon() -> Result<StatusIcon, String> {
 pub fn set_icon_callbacks(icon: &StatusIcon,
                           id_counter: StateHolder<usize>,
                           instances: StateHolder<HashMap<usize, GtkVisualizerInstance>>,
-                          data: Vec<SharedData>,
+                          sources: StateHolder<SourceTable>,
                           update_sender: Sender<UpdateMessage>,
-                          default_index: usize,
                           program_continue: ContinueState) {
     icon.set_tooltip_text("Visualizers");
+    // list the tempo and levels of every source that is being analysed
+    {
+        clone_local!(sources, program_continue);
+        let icon = icon.clone();
+        gtk::timeout_add(1000, move || {
+            let mut text = "Visualizers".to_string();
+            let sources = sources.borrow();
+            for (i, info) in sources.info.iter().enumerate() {
+                if let (&Some(ref info), Some(summary)) = (info, sources.summary(i)) {
+                    text.push_str(&format!("\n{}: {}", info.description, tempo_text(summary.bpm)));
+                    if let Some(short_term) = summary.short_term {
+                        text.push_str(&format!(", {:.1} LUFS", short_term));
+                    }
+                    text.push_str(&format!(", peak {:.1} dBTP", 20. * summary.peak_hold.max(1e-10).log10()));
+                }
+            }
+            icon.set_tooltip_text(&text);
+            gtk::Continue(program_continue.get())
+        });
+    }
     icon.connect_button_release_event(move |icon, ebtn| {
         if is_right_click(ebtn) {
             let time = ebtn.get_time();
@@ -53,7 +73,7 @@ pub fn set_icon_callbacks(icon: &StatusIcon,
             null_item.hide();
             right_click_menu.popup_easy(3, time);
             {
-                clone_local!(program_continue, id_counter, update_sender, data, instances);
+                clone_local!(program_continue, id_counter, update_sender, sources, instances);
                 right_click_menu.connect_hide(move |this| {
                     if let Some(selection) = this.get_active() {
                         match &selection.get_name().unwrap() as &str {
@@ -63,11 +83,11 @@ pub fn set_icon_callbacks(icon: &StatusIcon,
                                     (*instances.borrow_mut())
                                         .insert(newid,
                                                 GtkVisualizerConfig {
-                                                        index: default_index,
+                                                        follow_default: true,
                                                         ..GtkVisualizerConfig::default()
                                                     }
                                                     .to_instance(newid,
-                                                                 &data,
+                                                                 sources.clone(),
                                                                  update_sender.clone()));
                                 }
                                 *id_counter.borrow_mut() += 1;
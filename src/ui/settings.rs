@@ -1,12 +1,32 @@
 use gtk::prelude::*;
 use gtk::{Window, WindowType, WindowPosition, SpinButton, Orientation, Align};
-use shared_data::StateHolder;
+use shared_data::{SourceTable, StateHolder, Rc, RefCell};
 use std::sync::mpsc::Sender;
 use message::UpdateMessage;
-use drawing::{BarData, CircleData, GradientData, DrawingStyle, Color};
+use audio_input::ChannelPosition;
+use super::helpers::tempo_text;
+use drawing::{BarData, CircleData, GradientData, Draw, DrawingStyle, Color};
+use spectrum::{AmplitudeScale, AmplitudeSettings, ChannelMapping, FrequencyRange, FrequencyScale, NormalizerMode, NormalizerSettings, SmoothingSettings};
 use gdk;
 use gtk;
 
+static FREQUENCY_SCALE_CHOICES: [(&'static str, FrequencyScale); 5] =
+    [("Linear", FrequencyScale::Linear),
+     ("1/3 Octave", FrequencyScale::Octave(3)),
+     ("1/6 Octave", FrequencyScale::Octave(6)),
+     ("Mel", FrequencyScale::Mel),
+     ("Bark", FrequencyScale::Bark)];
+
+static AMPLITUDE_SCALE_CHOICES: [(&'static str, AmplitudeScale); 3] =
+    [("Linear", AmplitudeScale::Linear),
+     ("Square Root", AmplitudeScale::Sqrt),
+     ("Decibel", AmplitudeScale::Decibel)];
+
+static NORMALIZER_MODE_CHOICES: [(&'static str, NormalizerMode); 3] =
+    [("Off", NormalizerMode::Off),
+     ("Global", NormalizerMode::Global),
+     ("Per Band", NormalizerMode::PerBand)];
+
 trait ToGtkSettings {
     fn to_gtk_settings(&self) -> gtk::Box;
 }
@@ -15,44 +35,40 @@ impl ToGtkSettings for StateHolder<DrawingStyle> {
     fn to_gtk_settings(&self) -> gtk::Box {
         let sbox = gtk::Box::new(Orientation::Vertical, 5);
         sbox.set_margin_top(10);
-        match *self.borrow() {
-            DrawingStyle::Bars(ref bdata) => {
-                let double_sided = make_bool_changer!("Double Sided", double_sided, self, bars, bars_mut);
-                // reasonable enough for now i guess
-                let num_bars = make_usize_changer!("# Bars", 1, 8000, num_bars, self, bars, bars_mut);
-                let split_audio_channels = make_bool_changer!("Split Audio Channels", split_audio_channels, self, bars, bars_mut);
-                let max_bar_pieces_vertical = make_usize_changer!("Maximum Pieces per Bar", 1, 8000, max_bar_pieces_vertical, self, bars, bars_mut);
-                let bar_piece_width = make_f64_changer!("Bar Piece Width", 1., 8000., bar_piece_width, self, bars, bars_mut);
-                let bar_piece_height = make_f64_changer!("Bar Piece Height", 1., 8000., bar_piece_height, self, bars, bars_mut);
-                let bar_piece_horizontal_spacing = make_f64_changer!("Bar Piece Horizontal Spacing", 0., 8000., bar_piece_horizontal_spacing, self, bars, bars_mut);
-                let bar_piece_vertical_spacing = make_f64_changer!("Bar Piece Vertical Spacing", 0., 8000., bar_piece_vertical_spacing, self, bars, bars_mut);
-                let draw_color = make_color_changer!("Bar Draw Color", draw_color, self, bars, bars_mut);
-                let bg_color = make_color_changer!("Background Color", bg_color, self, bars, bars_mut);
-                let top_padding = make_f64_changer!("Top Padding", 1., 8000., top_padding, self, bars, bars_mut);
-                let bottom_padding = make_f64_changer!("Bottom Badding", 1., 8000., bottom_padding, self, bars, bars_mut);
-                let left_padding = make_f64_changer!("Left Padding", 0., 8000., left_padding, self, bars, bars_mut);
-                let right_padding = make_f64_changer!("Right Padding", 0., 8000., right_padding, self, bars, bars_mut);
-                sbox.add(&double_sided);
-                sbox.add(&num_bars);
-                sbox.add(&split_audio_channels);
-                sbox.add(&max_bar_pieces_vertical);
-                sbox.add(&bar_piece_width);
-                sbox.add(&bar_piece_height);
-                sbox.add(&bar_piece_horizontal_spacing);
-                sbox.add(&bar_piece_vertical_spacing);
-                sbox.add(&draw_color);
-                sbox.add(&bg_color);
-                sbox.add(&top_padding);
-                sbox.add(&bottom_padding);
-                sbox.add(&left_padding);
-                sbox.add(&right_padding);
-            }
-            DrawingStyle::Circle(ref cdata) => {
-
-            }
-            DrawingStyle::Gradient(ref gdata) => {
-
-            }
+        // the changers borrow the style themselves, so it can't stay borrowed in here
+        let is_bars = self.borrow().bars().is_some();
+        if is_bars {
+            let double_sided = make_bool_changer!("Double Sided", double_sided, self, bars, bars_mut);
+            // reasonable enough for now i guess
+            let num_bars = make_usize_changer!("# Bars", 1, 8000, num_bars, self, bars, bars_mut);
+            let split_audio_channels = make_bool_changer!("Split Audio Channels", split_audio_channels, self, bars, bars_mut);
+            let frequency_scale = new_choice_box("Frequency Scale", &FREQUENCY_SCALE_CHOICES, self.clone(), |s| &mut s.bars_mut().unwrap().frequency_scale);
+            let max_bar_pieces_vertical = make_usize_changer!("Maximum Pieces per Bar", 1, 8000, max_bar_pieces_vertical, self, bars, bars_mut);
+            let bar_piece_width = make_f64_changer!("Bar Piece Width", 1., 8000., bar_piece_width, self, bars, bars_mut);
+            let bar_piece_height = make_f64_changer!("Bar Piece Height", 1., 8000., bar_piece_height, self, bars, bars_mut);
+            let bar_piece_horizontal_spacing = make_f64_changer!("Bar Piece Horizontal Spacing", 0., 8000., bar_piece_horizontal_spacing, self, bars, bars_mut);
+            let bar_piece_vertical_spacing = make_f64_changer!("Bar Piece Vertical Spacing", 0., 8000., bar_piece_vertical_spacing, self, bars, bars_mut);
+            let draw_color = make_color_changer!("Bar Draw Color", draw_color, self, bars, bars_mut);
+            let bg_color = make_color_changer!("Background Color", bg_color, self, bars, bars_mut);
+            let top_padding = make_f64_changer!("Top Padding", 1., 8000., top_padding, self, bars, bars_mut);
+            let bottom_padding = make_f64_changer!("Bottom Badding", 1., 8000., bottom_padding, self, bars, bars_mut);
+            let left_padding = make_f64_changer!("Left Padding", 0., 8000., left_padding, self, bars, bars_mut);
+            let right_padding = make_f64_changer!("Right Padding", 0., 8000., right_padding, self, bars, bars_mut);
+            sbox.add(&double_sided);
+            sbox.add(&num_bars);
+            sbox.add(&split_audio_channels);
+            sbox.add(&frequency_scale);
+            sbox.add(&max_bar_pieces_vertical);
+            sbox.add(&bar_piece_width);
+            sbox.add(&bar_piece_height);
+            sbox.add(&bar_piece_horizontal_spacing);
+            sbox.add(&bar_piece_vertical_spacing);
+            sbox.add(&draw_color);
+            sbox.add(&bg_color);
+            sbox.add(&top_padding);
+            sbox.add(&bottom_padding);
+            sbox.add(&left_padding);
+            sbox.add(&right_padding);
         }
         sbox
     }
@@ -64,11 +80,18 @@ pub struct SettingsWindow {
 
 impl SettingsWindow {
     pub fn new(id: usize,
-               num_sources: usize,
+               sources: StateHolder<SourceTable>,
+               source: StateHolder<Option<String>>,
                index: StateHolder<usize>,
+               follow_default: StateHolder<bool>,
                x: StateHolder<usize>,
                y: StateHolder<usize>,
                style: StateHolder<DrawingStyle>,
+               channels: StateHolder<ChannelMapping>,
+               frequency_range: StateHolder<FrequencyRange>,
+               amplitude: StateHolder<AmplitudeSettings>,
+               normalizer: StateHolder<NormalizerSettings>,
+               smoothing: StateHolder<SmoothingSettings>,
                update_sender: Sender<UpdateMessage>)
         -> Self {
             let window = Window::new(WindowType::Toplevel);
@@ -85,22 +108,22 @@ impl SettingsWindow {
             let general_settings_page = gtk::Box::new(Orientation::Vertical, 5);
             general_settings_page.add(&x_control);
             general_settings_page.add(&y_control);
-            {
-                let bx = gtk::Box::new(Orientation::Horizontal, 0);
-                let label = gtk::Label::new(Some("Audio Source Index"));
-                label.set_halign(Align::Start);
-                label.set_margin_left(10);
-                let sb = SpinButton::new_with_range(0., (num_sources - 1) as f64, 1.);
-                sb.set_value(*index.borrow() as f64);
-                sb.connect_value_changed(move |sb| {
-                    let newval = sb.get_value_as_int() as usize;
-                    update_sender.send(UpdateMessage::ChangeMapping(id, *index.borrow(), newval)).unwrap();
-                    *index.borrow_mut() = newval;
-                });
-                bx.add(&label);
-                bx.add(&sb);
-                general_settings_page.add(&bx);
-            }
+            general_settings_page.add(&new_source_box(&window, id, sources.clone(), source, index.clone(), follow_default, update_sender.clone()));
+            general_settings_page.add(&new_channel_box(&window, id, sources.clone(), index.clone(), style.clone(), channels, update_sender));
+            general_settings_page.add(&new_tempo_box(&window, sources, index));
+            general_settings_page.add(&new_limit_box("Lowest Frequency (Hz)", 96000., frequency_range.clone(), |r| &mut r.min_hz));
+            general_settings_page.add(&new_limit_box("Highest Frequency (Hz)", 96000., frequency_range, |r| &mut r.max_hz));
+            general_settings_page.add(&new_choice_box("Amplitude Scale", &AMPLITUDE_SCALE_CHOICES, amplitude.clone(), |a| &mut a.scale));
+            general_settings_page.add(&new_f64_box("Amplitude Floor (dB)", -200., 0., amplitude.clone(), |a| &mut a.floor_db));
+            general_settings_page.add(&new_f64_box("Amplitude Ceiling (dB)", -200., 0., amplitude, |a| &mut a.ceiling_db));
+            general_settings_page.add(&new_choice_box("Auto Gain", &NORMALIZER_MODE_CHOICES, normalizer.clone(), |n| &mut n.mode));
+            general_settings_page.add(&new_f64_box("Auto Gain Target", 0., 1., normalizer.clone(), |n| &mut n.target));
+            general_settings_page.add(&new_f64_box("Auto Gain Response (ms)", 0., 60000., normalizer.clone(), |n| &mut n.response_ms));
+            general_settings_page.add(&new_f64_box("Auto Gain Percentile", 0., 100., normalizer.clone(), |n| &mut n.percentile));
+            general_settings_page.add(&new_fine_f64_box("Auto Gain Floor", 0., 1., 0.0001, 4, normalizer, |n| &mut n.floor));
+            general_settings_page.add(&new_f64_box("Attack Time (ms)", 0., 10000., smoothing.clone(), |s| &mut s.attack_ms));
+            general_settings_page.add(&new_f64_box("Release Time (ms)", 0., 10000., smoothing.clone(), |s| &mut s.release_ms));
+            general_settings_page.add(&new_f64_box("Gravity (0 is off)", 0., 10000., smoothing, |s| &mut s.gravity));
             add_tab(&notebook, "General", general_settings_page.upcast());
 
             let specific_page = style.to_gtk_settings();
@@ -115,6 +138,222 @@ impl SettingsWindow {
 }
 
 
+// lists the sources that are currently around, refreshed when they are plugged in or out
+// or the default changes
+fn new_source_box(window: &Window,
+                  id: usize,
+                  sources: StateHolder<SourceTable>,
+                  source: StateHolder<Option<String>>,
+                  index: StateHolder<usize>,
+                  follow_default: StateHolder<bool>,
+                  update_sender: Sender<UpdateMessage>)
+                  -> gtk::Box {
+    let outer = gtk::Box::new(Orientation::Vertical, 5);
+    let bx = gtk::Box::new(Orientation::Horizontal, 0);
+    let label = gtk::Label::new(Some("Audio Source"));
+    label.set_halign(Align::Start);
+    label.set_margin_left(10);
+    let combo = gtk::ComboBoxText::new();
+    let follow_bx = gtk::Box::new(Orientation::Horizontal, 0);
+    let follow_label = gtk::Label::new(Some("Follow Default Output"));
+    follow_label.set_halign(Align::Start);
+    follow_label.set_margin_left(10);
+    let follow_check = gtk::CheckButton::new();
+    follow_check.set_active(*follow_default.borrow());
+    combo.set_sensitive(!*follow_default.borrow());
+    // source index of each combo entry
+    let entries: StateHolder<Vec<usize>> = Rc::new(RefCell::new(Vec::new()));
+    // set while the entries get rebuilt, so that doesn't count as picking a source
+    let refreshing = Rc::new(RefCell::new(false));
+    let fill = {
+        clone_local!(combo, sources, index, entries, refreshing);
+        Rc::new(move || {
+            *refreshing.borrow_mut() = true;
+            combo.remove_all();
+            entries.borrow_mut().clear();
+            for (i, info) in sources.borrow().info.iter().enumerate() {
+                if let Some(ref info) = *info {
+                    combo.append_text(&format!("{}: {}", i, info.description));
+                    entries.borrow_mut().push(i);
+                }
+            }
+            if let Some(pos) = entries.borrow().iter().position(|&i| i == *index.borrow()) {
+                combo.set_active(pos as i32);
+            }
+            *refreshing.borrow_mut() = false;
+        })
+    };
+    fill();
+    {
+        clone_local!(source, index, sources, update_sender, combo, fill);
+        follow_check.connect_toggled(move |btn| {
+            let follow = btn.get_active();
+            *follow_default.borrow_mut() = follow;
+            combo.set_sensitive(!follow);
+            let default_index = sources.borrow().default_index;
+            match default_index {
+                Some(newval) if follow => {
+                    if let Some((_, info)) = sources.borrow().get(newval) {
+                        *source.borrow_mut() = Some(info.name.clone());
+                    }
+                    update_sender.send(UpdateMessage::ChangeMapping(id, *index.borrow(), newval)).unwrap();
+                    *index.borrow_mut() = newval;
+                    fill();
+                }
+                _ => {}
+            }
+            update_sender.send(UpdateMessage::FollowDefault(id, follow)).unwrap();
+        });
+    }
+    {
+        clone_local!(index, sources, entries, refreshing);
+        combo.connect_changed(move |c| {
+            let pos = c.get_active();
+            if *refreshing.borrow() || pos < 0 {
+                return;
+            }
+            if let Some(&newval) = entries.borrow().get(pos as usize) {
+                let name = sources.borrow().get(newval).map(|(_, info)| info.name.clone());
+                // picking the same entry again still reattaches a missing source
+                if newval != *index.borrow() || name != *source.borrow() {
+                    update_sender.send(UpdateMessage::ChangeMapping(id, *index.borrow(), newval)).unwrap();
+                    *index.borrow_mut() = newval;
+                    *source.borrow_mut() = name;
+                }
+            }
+        });
+    }
+    {
+        let open = Rc::new(RefCell::new(true));
+        {
+            let open = open.clone();
+            window.connect_destroy(move |_| *open.borrow_mut() = false);
+        }
+        let mut generation = sources.borrow().generation;
+        gtk::timeout_add(500, move || {
+            if *open.borrow() && sources.borrow().generation != generation {
+                generation = sources.borrow().generation;
+                fill();
+            }
+            gtk::Continue(*open.borrow())
+        });
+    }
+    bx.add(&label);
+    bx.add(&combo);
+    bx.set_homogeneous(true);
+    follow_bx.add(&follow_label);
+    follow_bx.add(&follow_check);
+    follow_bx.set_homogeneous(true);
+    outer.add(&follow_bx);
+    outer.add(&bx);
+    outer
+}
+
+// the channel mappings that fit the instance's source, refreshed when the source changes
+fn new_channel_box(window: &Window,
+                   id: usize,
+                   sources: StateHolder<SourceTable>,
+                   index: StateHolder<usize>,
+                   style: StateHolder<DrawingStyle>,
+                   channels: StateHolder<ChannelMapping>,
+                   update_sender: Sender<UpdateMessage>)
+                   -> gtk::Box {
+    let bx = gtk::Box::new(Orientation::Horizontal, 0);
+    let label = gtk::Label::new(Some("Channels"));
+    label.set_halign(Align::Start);
+    label.set_margin_left(10);
+    let combo = gtk::ComboBoxText::new();
+    let entries: StateHolder<Vec<ChannelMapping>> = Rc::new(RefCell::new(Vec::new()));
+    let refreshing = Rc::new(RefCell::new(false));
+    let fill = {
+        clone_local!(combo, sources, index, channels, entries, refreshing);
+        move || {
+            *refreshing.borrow_mut() = true;
+            combo.remove_all();
+            let positions = match sources.borrow().get(*index.borrow()) {
+                Some((_, info)) => info.positions.clone(),
+                None => vec![ChannelPosition::Mono],
+            };
+            let mut choices = ChannelMapping::choices(&positions);
+            // a mapping that doesn't fit still shows, the instance says what is wrong with it
+            let current = *channels.borrow();
+            if !choices.contains(&current) {
+                choices.push(current);
+            }
+            for mapping in choices.iter() {
+                combo.append_text(&mapping.name());
+            }
+            if let Some(pos) = choices.iter().position(|&m| m == current) {
+                combo.set_active(pos as i32);
+            }
+            *entries.borrow_mut() = choices;
+            *refreshing.borrow_mut() = false;
+        }
+    };
+    fill();
+    {
+        clone_local!(channels, entries, refreshing);
+        combo.connect_changed(move |c| {
+            let pos = c.get_active();
+            if *refreshing.borrow() || pos < 0 {
+                return;
+            }
+            if let Some(&mapping) = entries.borrow().get(pos as usize) {
+                *channels.borrow_mut() = mapping;
+                let content = style.borrow().content().union(mapping.content());
+                update_sender.send(UpdateMessage::Content(id, content)).unwrap();
+            }
+        });
+    }
+    {
+        let open = Rc::new(RefCell::new(true));
+        {
+            let open = open.clone();
+            window.connect_destroy(move |_| *open.borrow_mut() = false);
+        }
+        let mut shown = (sources.borrow().generation, *index.borrow());
+        gtk::timeout_add(500, move || {
+            let current = (sources.borrow().generation, *index.borrow());
+            if *open.borrow() && current != shown {
+                shown = current;
+                fill();
+            }
+            gtk::Continue(*open.borrow())
+        });
+    }
+    bx.add(&label);
+    bx.add(&combo);
+    bx.set_homogeneous(true);
+    bx
+}
+
+// tempo of the instance's source, kept up to date while the window is open
+fn new_tempo_box(window: &Window, sources: StateHolder<SourceTable>, index: StateHolder<usize>) -> gtk::Box {
+    let bx = gtk::Box::new(Orientation::Horizontal, 0);
+    let label = gtk::Label::new(Some("Tempo"));
+    label.set_halign(Align::Start);
+    label.set_margin_left(10);
+    let value = gtk::Label::new(Some(&tempo_text(sources.borrow().tempo(*index.borrow()))));
+    {
+        let open = Rc::new(RefCell::new(true));
+        {
+            let open = open.clone();
+            window.connect_destroy(move |_| *open.borrow_mut() = false);
+        }
+        clone_local!(value);
+        gtk::timeout_add(500, move || {
+            if *open.borrow() {
+                value.set_text(&tempo_text(sources.borrow().tempo(*index.borrow())));
+            }
+            gtk::Continue(*open.borrow())
+        });
+    }
+    bx.add(&label);
+    bx.add(&value);
+    bx.set_homogeneous(true);
+    bx
+}
+
 fn new_dimension_box(name: &str, dim_var: StateHolder<usize>, max: usize) -> gtk::Box {
     let bx = gtk::Box::new(Orientation::Horizontal, 0);
     let label = gtk::Label::new(Some(name));
@@ -135,6 +374,98 @@ fn new_dimension_sb(dim_var: StateHolder<usize>, max: usize) -> SpinButton {
     sb
 }
 
+// spin button for one f64 field of some shared settings
+fn new_f64_box<T, F>(name: &str, min: f64, max: f64, settings: StateHolder<T>, field: F) -> gtk::Box
+    where T: 'static,
+          F: Fn(&mut T) -> &mut f64 + 'static
+{
+    new_fine_f64_box(name, min, max, 1., 2, settings, field)
+}
+
+// same with a custom step and number of digits, for fields that are tiny fractions
+fn new_fine_f64_box<T, F>(name: &str,
+                          min: f64,
+                          max: f64,
+                          step: f64,
+                          digits: u32,
+                          settings: StateHolder<T>,
+                          field: F)
+                          -> gtk::Box
+    where T: 'static,
+          F: Fn(&mut T) -> &mut f64 + 'static
+{
+    let bx = gtk::Box::new(Orientation::Horizontal, 0);
+    let label = gtk::Label::new(Some(name));
+    label.set_halign(Align::Start);
+    label.set_margin_left(10);
+    let sb = SpinButton::new_with_range(min, max, step);
+    sb.set_digits(digits);
+    sb.set_value(*field(&mut *settings.borrow_mut()));
+    sb.connect_value_changed(move |sb| {
+        *field(&mut *settings.borrow_mut()) = sb.get_value();
+    });
+    bx.add(&label);
+    bx.add(&sb);
+    bx.set_homogeneous(true);
+    bx
+}
+
+// for optional limits, 0 stands for no limit
+fn new_limit_box<T, F>(name: &str, max: f64, settings: StateHolder<T>, field: F) -> gtk::Box
+    where T: 'static,
+          F: Fn(&mut T) -> &mut Option<f64> + 'static
+{
+    let bx = gtk::Box::new(Orientation::Horizontal, 0);
+    let label = gtk::Label::new(Some(name));
+    label.set_halign(Align::Start);
+    label.set_margin_left(10);
+    let sb = SpinButton::new_with_range(0., max, 1.);
+    sb.set_digits(2);
+    sb.set_value(field(&mut *settings.borrow_mut()).unwrap_or(0.));
+    sb.connect_value_changed(move |sb| {
+        let value = sb.get_value();
+        *field(&mut *settings.borrow_mut()) = if value > 0. { Some(value) } else { None };
+    });
+    bx.add(&label);
+    bx.add(&sb);
+    bx.set_homogeneous(true);
+    bx
+}
+
+// combo box picking one field of some shared settings out of (label, value) pairs
+fn new_choice_box<T, V, F>(name: &str,
+                           choices: &'static [(&'static str, V)],
+                           settings: StateHolder<T>,
+                           field: F)
+                           -> gtk::Box
+    where T: 'static,
+          V: Copy + PartialEq + 'static,
+          F: Fn(&mut T) -> &mut V + 'static
+{
+    let bx = gtk::Box::new(Orientation::Horizontal, 0);
+    let label = gtk::Label::new(Some(name));
+    label.set_halign(Align::Start);
+    label.set_margin_left(10);
+    let combo = gtk::ComboBoxText::new();
+    for &(l, _) in choices.iter() {
+        combo.append_text(l);
+    }
+    let current = *field(&mut *settings.borrow_mut());
+    if let Some(idx) = choices.iter().position(|&(_, v)| v == current) {
+        combo.set_active(idx as i32);
+    }
+    combo.connect_changed(move |c| {
+        let idx = c.get_active();
+        if idx >= 0 && (idx as usize) < choices.len() {
+            *field(&mut *settings.borrow_mut()) = choices[idx as usize].1;
+        }
+    });
+    bx.add(&label);
+    bx.add(&combo);
+    bx.set_homogeneous(true);
+    bx
+}
+
 fn add_tab(notebook: &gtk::Notebook, title: &str, widget: gtk::Widget) {
     let tab = gtk::Label::new(Some(title));
     tab.show_all();